@@ -0,0 +1,81 @@
+//! Turns the function catalogs in `catalog/` into static tables of functions and their signatures that are compiled
+//! into the server. Nothing here needs the network: the catalogs are checked in. The Snowflake one is a curated subset
+//! of the documentation until it is regenerated with `dbt-lsp catalog`, see `catalog/README.md`.
+
+use std::{env, fmt::Write, fs, path::Path};
+
+#[path = "src/functions/catalog.rs"]
+mod catalog;
+
+use catalog::{Catalog, CATALOG_VERSION};
+
+fn kind(category: &str) -> &'static str {
+    match category {
+        "aggregate" => "FunctionKind::Aggregate",
+        "window" => "FunctionKind::Window",
+        "table" => "FunctionKind::Table",
+        _ => "FunctionKind::Scalar",
+    }
+}
+
+fn generate(catalog: &str, path: &str, table: &str) -> String {
+    let mut catalog: Catalog =
+        serde_json::from_str(catalog).unwrap_or_else(|e| panic!("{} is not a valid catalog: {}", path, e));
+    assert_eq!(
+        catalog.version, CATALOG_VERSION,
+        "{} has version {} of the catalog format, regenerate it with `dbt-lsp catalog`",
+        path, catalog.version
+    );
+    let functions = &mut catalog.functions;
+    for function in functions.iter_mut() {
+        function.name = function.name.to_uppercase();
+    }
+    // Looked up by binary search
+    functions.sort_by(|a, b| a.name.cmp(&b.name));
+    functions.dedup_by(|a, b| a.name == b.name);
+
+    let mut out = format!("pub static {}: &[Function] = &[\n", table);
+    for function in functions.iter() {
+        writeln!(out, "    Function {{").unwrap();
+        writeln!(out, "        name: {:?},", function.name).unwrap();
+        writeln!(out, "        kind: {},", kind(&function.category)).unwrap();
+        writeln!(out, "        summary: {:?},", function.summary).unwrap();
+        writeln!(out, "        returns: {:?},", function.returns).unwrap();
+        writeln!(out, "        signatures: &[").unwrap();
+        for signature in &function.signatures {
+            writeln!(out, "            Signature {{").unwrap();
+            writeln!(out, "                label: {:?},", signature.syntax).unwrap();
+            writeln!(out, "                arguments: &[").unwrap();
+            for argument in &signature.arguments {
+                writeln!(
+                    out,
+                    "                    Argument {{ name: {:?}, optional: {}, variadic: {} }},",
+                    argument.name, argument.optional, argument.variadic
+                )
+                .unwrap();
+            }
+            writeln!(out, "                ],").unwrap();
+            writeln!(out, "            }},").unwrap();
+        }
+        writeln!(out, "        ],").unwrap();
+        writeln!(out, "        columns: &{:?},", function.columns).unwrap();
+        writeln!(out, "    }},").unwrap();
+    }
+    out.push_str("];\n");
+    out
+}
+
+/// The catalogs, and the tables of `crate::functions` they are generated into
+const CATALOGS: &[(&str, &str)] = &[("snowflake", "SNOWFLAKE")];
+
+fn main() {
+    println!("cargo:rerun-if-changed=src/functions/catalog.rs");
+    let out_dir = env::var("OUT_DIR").unwrap();
+    for (catalog, table) in CATALOGS {
+        let path = format!("catalog/{}.json", catalog);
+        println!("cargo:rerun-if-changed={}", path);
+        let catalog = fs::read_to_string(&path).unwrap_or_else(|e| panic!("could not read {}: {}", path, e));
+        let generated = generate(&catalog, &path, table);
+        fs::write(Path::new(&out_dir).join(format!("{}_functions.rs", table.to_lowercase())), generated).unwrap();
+    }
+}
@@ -0,0 +1,345 @@
+use std::ops;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use similar::{capture_diff_slices_deadline, Algorithm, DiffTag};
+use tower_lsp::lsp_types::{
+    CodeAction, CodeActionDisabled, CodeActionKind, CodeActionOrCommand, Diagnostic,
+    DocumentChanges, NumberOrString, OneOf, OptionalVersionedTextDocumentIdentifier, Range,
+    TextDocumentEdit, TextEdit, Url, WorkspaceEdit,
+};
+
+use crate::{jinja_parser::JinjaParser, sqlfluff::Sqlfluff, utils::Span};
+
+/// A change sqlfluff made to the rendered SQL, mapped back to the template
+pub struct TemplateEdit {
+    pub edit: TextEdit,
+    /// Whether the change is to SQL generated by a Jinja expression, which can not be made in the template
+    pub generated: bool,
+}
+
+/// The byte offset of every char in `text`, followed by the length of `text`
+fn char_offsets(text: &str) -> Vec<usize> {
+    text.char_indices()
+        .map(|(offset, _)| offset)
+        .chain([text.len()])
+        .collect()
+}
+
+/// The char ranges that differ between the rendered SQL and the fixed SQL. Chars are matched regardless of case, so a
+/// keyword that only changes case is not matched with the chars of other words, and is then changed as a whole.
+fn changes(rendered: &[char], fixed: &[char]) -> Vec<(ops::Range<usize>, ops::Range<usize>)> {
+    let fold = |chars: &[char]| chars.iter().map(char::to_ascii_lowercase).collect::<Vec<_>>();
+    let deadline = Instant::now() + Duration::from_secs(1);
+    let mut changes = vec![];
+    for op in capture_diff_slices_deadline(Algorithm::Myers, &fold(rendered), &fold(fixed), Some(deadline)) {
+        let (old, new) = (op.old_range(), op.new_range());
+        if op.tag() != DiffTag::Equal {
+            changes.push((old, new));
+            continue;
+        }
+        // Runs of chars that only differ in case
+        let mut i = 0;
+        while i < old.len() {
+            let start = i;
+            while i < old.len() && rendered[old.start + i] != fixed[new.start + i] {
+                i += 1;
+            }
+            if start < i {
+                changes.push((old.start + start..old.start + i, new.start + start..new.start + i));
+            }
+            i += 1;
+        }
+    }
+    changes
+}
+
+/// The chars of the template to delete for a deletion of whitespace in the rendered SQL, or `None` if it is all
+/// padding around Jinja output. Any chars of a run of the same whitespace can be deleted, and the padding, which is not
+/// in the template, goes first. Otherwise the whitespace written in the template would go, and the padding stay.
+fn written_whitespace(
+    jinja_parse: &JinjaParser,
+    rendered: &[char],
+    offsets: &[usize],
+    old: ops::Range<usize>,
+) -> Option<ops::Range<usize>> {
+    let c = rendered[old.start];
+    if !c.is_whitespace() || rendered[old.clone()].iter().any(|&other| other != c) {
+        return Some(old);
+    }
+    let (mut start, mut end) = (old.start, old.end);
+    while start > 0 && rendered[start - 1] == c {
+        start -= 1;
+    }
+    while end < rendered.len() && rendered[end] == c {
+        end += 1;
+    }
+    let written: Vec<usize> = (start..end)
+        .filter(|&i| !jinja_parse.is_padding(offsets[i], offsets[i + 1]))
+        .collect();
+    let count = old.len().saturating_sub(end - start - written.len());
+    match written.last() {
+        _ if count == 0 => None,
+        Some(&last) if last + 1 - written[0] == written.len() => Some(last + 1 - count..last + 1),
+        _ => Some(old),
+    }
+}
+
+/// Diffs the rendered SQL against the fixed SQL and maps every change back to the template
+pub fn template_edits(jinja_parse: &JinjaParser, fixed: &str) -> Vec<TemplateEdit> {
+    let rendered = jinja_parse.output();
+    let (rendered_chars, fixed_chars): (Vec<char>, Vec<char>) = (rendered.chars().collect(), fixed.chars().collect());
+    // The diff counts chars, while the source map works with byte offsets
+    let rendered_offsets = char_offsets(rendered);
+    let fixed_offsets = char_offsets(fixed);
+    changes(&rendered_chars, &fixed_chars)
+        .into_iter()
+        .filter_map(|(old, new)| {
+            let old = if new.is_empty() {
+                written_whitespace(jinja_parse, &rendered_chars, &rendered_offsets, old)?
+            } else {
+                old
+            };
+            let (start, end) = (rendered_offsets[old.start], rendered_offsets[old.end]);
+            let new_text = &fixed[fixed_offsets[new.start]..fixed_offsets[new.end]];
+            // The padding is not in the template, so whitespace that replaces it could only be added next to it, and
+            // the next fix would ask for the same change again
+            if start < end && jinja_parse.is_padding(start, end) && new_text.trim().is_empty() {
+                return None;
+            }
+            let span = jinja_parse.translate_span(start, end)?;
+            Some(TemplateEdit {
+                edit: TextEdit {
+                    range: Span::from_span(span).to_range(),
+                    new_text: new_text.to_string(),
+                },
+                generated: jinja_parse.touches_generated(start, end),
+            })
+        })
+        .collect()
+}
+
+fn overlaps(a: &Range, b: &Range) -> bool {
+    a.start <= b.end && b.start <= a.end
+}
+
+fn workspace_edit(uri: &Url, version: i32, edits: Vec<TextEdit>) -> WorkspaceEdit {
+    WorkspaceEdit {
+        document_changes: Some(DocumentChanges::Edits(vec![TextDocumentEdit {
+            text_document: OptionalVersionedTextDocumentIdentifier {
+                uri: uri.clone(),
+                version: Some(version),
+            },
+            edits: edits.into_iter().map(OneOf::Left).collect(),
+        }])),
+        ..WorkspaceEdit::default()
+    }
+}
+
+/// A "Fix this violation" action for the sqlfluff diagnostic. Only the changes sqlfluff makes within the
+/// diagnostic's range are applied, and if one of them is to SQL generated by Jinja the action is disabled.
+fn fix_violation(
+    uri: &Url,
+    version: i32,
+    edits: &[TemplateEdit],
+    diagnostic: &Diagnostic,
+    code: &str,
+) -> Option<CodeAction> {
+    let edits: Vec<&TemplateEdit> = edits
+        .iter()
+        .filter(|edit| overlaps(&edit.edit.range, &diagnostic.range))
+        .collect();
+    if edits.is_empty() {
+        return None;
+    }
+
+    let mut action = CodeAction {
+        title: format!("Fix this violation ({})", code),
+        kind: Some(CodeActionKind::QUICKFIX),
+        diagnostics: Some(vec![diagnostic.clone()]),
+        ..CodeAction::default()
+    };
+    if edits.iter().any(|edit| edit.generated) {
+        action.disabled = Some(CodeActionDisabled {
+            reason: "The fix would change SQL generated by Jinja".into(),
+        });
+    } else {
+        action.is_preferred = Some(true);
+        let edits = edits.into_iter().map(|edit| edit.edit.clone()).collect();
+        action.edit = Some(workspace_edit(uri, version, edits));
+    }
+    Some(action)
+}
+
+/// A "Fix all in file" action. Changes to SQL generated by Jinja are left out.
+fn fix_all(uri: &Url, version: i32, edits: &[TemplateEdit]) -> Option<CodeAction> {
+    let edits: Vec<TextEdit> = edits
+        .iter()
+        .filter(|edit| !edit.generated)
+        .map(|edit| edit.edit.clone())
+        .collect();
+    if edits.is_empty() {
+        return None;
+    }
+    Some(CodeAction {
+        title: "Fix all in file".into(),
+        kind: Some(CodeActionKind::SOURCE_FIX_ALL),
+        edit: Some(workspace_edit(uri, version, edits)),
+        ..CodeAction::default()
+    })
+}
+
+/// The sqlfluff fixes for the diagnostics the client asks about, optionally limited to the `only` kinds.
+/// sqlfluff fixes the whole file once, and each diagnostic gets the changes within its range.
+pub async fn code_actions(
+    uri: &Url,
+    version: i32,
+    src: &str,
+    path: Option<&Path>,
+    diagnostics: &[Diagnostic],
+    only: Option<&[CodeActionKind]>,
+    sqlfluff: &Sqlfluff,
+) -> Vec<CodeActionOrCommand> {
+    let wants = |kind: &CodeActionKind| {
+        only.is_none_or(|only| {
+            only.iter()
+                .any(|only| kind.as_str().starts_with(only.as_str()))
+        })
+    };
+    let sqlfluff_diagnostics: Vec<(&Diagnostic, &str)> = diagnostics
+        .iter()
+        .filter(|diagnostic| diagnostic.source.as_deref() == Some("sqlfluff"))
+        .filter_map(|diagnostic| match &diagnostic.code {
+            Some(NumberOrString::String(code)) => Some((diagnostic, code.as_str())),
+            _ => None,
+        })
+        .collect();
+    let quick_fixes = wants(&CodeActionKind::QUICKFIX) && !sqlfluff_diagnostics.is_empty();
+    // Offered next to the quick fixes, or when the client asks for it e.g. on save
+    let fix_all_in_file =
+        wants(&CodeActionKind::SOURCE_FIX_ALL) && (!sqlfluff_diagnostics.is_empty() || only.is_some());
+    if !quick_fixes && !fix_all_in_file {
+        return vec![];
+    }
+
+    let mut jinja_parse = JinjaParser::new(src);
+    if jinja_parse.render_jinja().is_err() || jinja_parse.output().trim().is_empty() {
+        return vec![];
+    }
+    let Ok(fixed) = sqlfluff.fix(jinja_parse.output(), path, None).await else {
+        return vec![];
+    };
+    let edits = template_edits(&jinja_parse, &fixed);
+
+    let mut actions = vec![];
+    if quick_fixes {
+        actions.extend(
+            sqlfluff_diagnostics
+                .iter()
+                .filter_map(|(diagnostic, code)| fix_violation(uri, version, &edits, diagnostic, code))
+                .map(CodeActionOrCommand::CodeAction),
+        );
+    }
+    if fix_all_in_file {
+        actions.extend(fix_all(uri, version, &edits).map(CodeActionOrCommand::CodeAction));
+    }
+    actions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tower_lsp::lsp_types::Position;
+
+    fn range(start: (u32, u32), end: (u32, u32)) -> Range {
+        Range::new(Position::new(start.0, start.1), Position::new(end.0, end.1))
+    }
+
+    #[test]
+    fn test_template_edits() {
+        let src = "SELECT a,b\nfrom {{ ref('orders') }}\n";
+        let mut jinja_parse = JinjaParser::new(src);
+        jinja_parse.render_jinja().unwrap();
+        assert_eq!(jinja_parse.output(), "SELECT a,b\nfrom  orders \n");
+
+        let edits = template_edits(&jinja_parse, "SELECT a, b\nFROM ORDERS\n");
+        let edits: Vec<_> = edits
+            .iter()
+            .map(|edit| (edit.edit.range, edit.edit.new_text.as_str(), edit.generated))
+            .collect();
+        assert_eq!(
+            edits,
+            [
+                (range((0, 9), (0, 9)), " ", false),
+                (range((1, 0), (1, 4)), "FROM", false),
+                // The model name only exists in the rendered SQL, so the change covers the whole expression
+                (range((1, 5), (1, 24)), "ORDERS", true),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_padding_edits() {
+        let edits = |src: &str, fixed: &str| {
+            let mut jinja_parse = JinjaParser::new(src);
+            jinja_parse.render_jinja().unwrap();
+            template_edits(&jinja_parse, fixed)
+                .into_iter()
+                .map(|edit| (edit.edit.range, edit.edit.new_text))
+                .collect::<Vec<_>>()
+        };
+        // The padding goes before the spaces written in the template
+        assert_eq!(
+            edits("select a from  {{ ref('orders') }}\n", "select a from orders\n"),
+            [(range((0, 14), (0, 15)), String::new())]
+        );
+        // Expressions next to SQL and to each other keep their padding
+        assert_eq!(edits("select a from{{ ref('orders') }}\n", "select a from orders\n"), []);
+        assert_eq!(edits("select * from {{ ref('a') }}{{ ref('b') }}", "select * from a b\n"), []);
+        assert_eq!(
+            edits("select a from{{ ref('orders') }}where a>1", "SELECT a from orders where a > 1"),
+            [
+                (range((0, 0), (0, 6)), "SELECT".to_string()),
+                (range((0, 39), (0, 39)), " ".to_string()),
+                (range((0, 40), (0, 40)), " ".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_split_fixes() {
+        let uri = Url::parse("file:///project/models/orders.sql").unwrap();
+        let mut jinja_parse = JinjaParser::new("select a,b\nfrom {{ ref('orders') }}\n");
+        jinja_parse.render_jinja().unwrap();
+        let edits = template_edits(&jinja_parse, "SELECT a, b\nFROM ORDERS\n");
+        let diagnostic = |range| Diagnostic {
+            range,
+            ..Diagnostic::default()
+        };
+        let new_texts = |action: &CodeAction| -> Vec<String> {
+            match &action.edit.as_ref().unwrap().document_changes {
+                Some(DocumentChanges::Edits(edits)) => edits[0]
+                    .edits
+                    .iter()
+                    .map(|edit| match edit {
+                        OneOf::Left(edit) => edit.new_text.clone(),
+                        OneOf::Right(edit) => edit.text_edit.new_text.clone(),
+                    })
+                    .collect(),
+                _ => vec![],
+            }
+        };
+
+        // Each violation gets the changes within its range
+        let keyword = fix_violation(&uri, 1, &edits, &diagnostic(range((0, 0), (0, 6))), "CP01").unwrap();
+        assert_eq!(new_texts(&keyword), ["SELECT"]);
+        let comma = fix_violation(&uri, 1, &edits, &diagnostic(range((0, 9), (0, 9))), "LT01").unwrap();
+        assert_eq!(new_texts(&comma), [" "]);
+        let reference = fix_violation(&uri, 1, &edits, &diagnostic(range((1, 5), (1, 24))), "CP02").unwrap();
+        assert!(reference.disabled.is_some() && reference.edit.is_none());
+        assert!(fix_violation(&uri, 1, &edits, &diagnostic(range((2, 0), (2, 0))), "LT12").is_none());
+
+        // Fixing the whole file leaves out the generated SQL
+        assert_eq!(new_texts(&fix_all(&uri, 1, &edits).unwrap()), ["SELECT", " ", "FROM"]);
+    }
+}
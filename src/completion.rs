@@ -0,0 +1,100 @@
+use tower_lsp::lsp_types::{
+    CompletionItem, CompletionItemKind, CompletionItemLabelDetails, Documentation, InsertTextFormat,
+};
+
+use crate::{
+    dialect::Dialect,
+    functions::{self, Argument, Function, FunctionKind},
+};
+
+/// The arguments of a signature in short, like `(input_expr, [scale_expr])`
+fn short_arguments(arguments: &[Argument]) -> String {
+    let arguments: Vec<String> = arguments
+        .iter()
+        .map(|argument| {
+            let name = if argument.variadic {
+                format!("{}...", argument.name)
+            } else {
+                argument.name.to_string()
+            };
+            if argument.optional {
+                format!("[{}]", name)
+            } else {
+                name
+            }
+        })
+        .collect();
+    format!("({})", arguments.join(", "))
+}
+
+/// A call of the function with a tab stop for each required argument of its first signature
+fn call_snippet(function: &Function) -> String {
+    let arguments: Vec<String> = function
+        .signatures
+        .first()
+        .map(|signature| signature.arguments)
+        .unwrap_or_default()
+        .iter()
+        .filter(|argument| !argument.optional)
+        .enumerate()
+        .map(|(i, argument)| format!("${{{}:{}}}", i + 1, argument.name))
+        .collect();
+    format!("{}({})", function.name, arguments.join(", "))
+}
+
+fn function_completion(function: &Function) -> CompletionItem {
+    let signature = function.signatures.first();
+    CompletionItem {
+        label: function.name.to_string(),
+        label_details: signature.map(|signature| CompletionItemLabelDetails {
+            detail: Some(short_arguments(signature.arguments)),
+            description: function.returns.map(str::to_string),
+        }),
+        kind: Some(match function.kind {
+            FunctionKind::Table => CompletionItemKind::CLASS,
+            FunctionKind::Scalar | FunctionKind::Aggregate | FunctionKind::Window => CompletionItemKind::FUNCTION,
+        }),
+        detail: signature.map(|signature| signature.label.to_string()),
+        documentation: Some(Documentation::String(function.summary.to_string())),
+        insert_text: Some(call_snippet(function)),
+        insert_text_format: Some(InsertTextFormat::SNIPPET),
+        ..Default::default()
+    }
+}
+
+/// Completions for the built-in functions of the dialect
+pub fn function_completions(dialect: Dialect) -> Vec<CompletionItem> {
+    functions::catalog(dialect).iter().map(function_completion).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_function_completions() {
+        let completions = function_completions(Dialect::Snowflake);
+        let round = completions.iter().find(|item| item.label == "ROUND").unwrap();
+        assert_eq!(
+            round.label_details.as_ref().and_then(|details| details.detail.as_deref()),
+            Some("(input_expr, [scale_expr], [rounding_mode])")
+        );
+        assert_eq!(round.insert_text.as_deref(), Some("ROUND(${1:input_expr})"));
+        assert_eq!(
+            round.detail.as_deref(),
+            Some("ROUND( <input_expr> [ , <scale_expr> [ , <rounding_mode> ] ] )")
+        );
+
+        let coalesce = completions.iter().find(|item| item.label == "COALESCE").unwrap();
+        assert_eq!(
+            coalesce.label_details.as_ref().and_then(|details| details.detail.as_deref()),
+            Some("(expr1, expr2...)")
+        );
+        let array_agg = completions.iter().find(|item| item.label == "ARRAY_AGG").unwrap();
+        assert_eq!(
+            array_agg.label_details.as_ref().and_then(|details| details.description.as_deref()),
+            Some("ARRAY")
+        );
+        assert!(function_completions(Dialect::BigQuery).is_empty());
+    }
+}
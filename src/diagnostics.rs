@@ -0,0 +1,212 @@
+use std::{collections::HashMap, future::Future, sync::Arc, time::Duration};
+
+use tokio::{sync::Mutex, task::JoinHandle};
+use tower_lsp::lsp_types::Url;
+
+struct Run {
+    version: i32,
+    handle: JoinHandle<()>,
+    /// Held while publishing, and shared by every run of the document, so that they publish one at a time
+    publishing: Arc<Mutex<()>>,
+}
+
+/// Runs the diagnostics of each document after a debounce, one run per document at a time.
+/// Scheduling a new run aborts the previous one for the same document, which also kills any `sqlfluff` child it was waiting on.
+#[derive(Default)]
+pub struct DiagnosticsScheduler {
+    runs: Arc<Mutex<HashMap<Url, Run>>>,
+}
+
+impl DiagnosticsScheduler {
+    /// Waits for `debounce`, then runs `compute` and hands the result to `publish`, unless a newer version of the document
+    /// was scheduled in the meantime.
+    pub async fn schedule<T, C, P, F>(
+        &self,
+        uri: Url,
+        version: i32,
+        debounce: Duration,
+        compute: C,
+        publish: P,
+    ) where
+        T: Send + 'static,
+        C: Future<Output = T> + Send + 'static,
+        P: FnOnce(T) -> F + Send + 'static,
+        F: Future<Output = ()> + Send,
+    {
+        let mut runs = self.runs.lock().await;
+        if let Some(run) = runs.get(&uri) {
+            if run.version > version {
+                return;
+            }
+            run.handle.abort();
+        }
+        let publishing = runs.get(&uri).map(|run| run.publishing.clone()).unwrap_or_default();
+
+        let task_runs = self.runs.clone();
+        let task_uri = uri.clone();
+        let task_publishing = publishing.clone();
+        let handle = tokio::spawn(async move {
+            tokio::time::sleep(debounce).await;
+            let diagnostics = compute.await;
+
+            // A newer run checks its version only once this one has published, so the older diagnostics cannot
+            // replace its own. Only this document waits on the client, the other documents' runs go on.
+            let _publishing = task_publishing.lock().await;
+            let latest = matches!(task_runs.lock().await.get(&task_uri), Some(run) if run.version == version);
+            if latest {
+                publish(diagnostics).await;
+            }
+        });
+        runs.insert(
+            uri,
+            Run {
+                version,
+                handle,
+                publishing,
+            },
+        );
+    }
+
+    /// Aborts the pending run of a document and forgets about it
+    pub async fn cancel(&self, uri: &Url) {
+        if let Some(run) = self.runs.lock().await.remove(uri) {
+            run.handle.abort();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::mpsc;
+    use tower_lsp::lsp_types::{Diagnostic, Range};
+
+    fn diagnostic(message: &str) -> Vec<Diagnostic> {
+        vec![Diagnostic::new_simple(Range::default(), message.into())]
+    }
+
+    #[tokio::test]
+    async fn test_superseded_runs_are_dropped() {
+        let scheduler = DiagnosticsScheduler::default();
+        let uri = Url::parse("file:///models/customers.sql").unwrap();
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+
+        for version in 1..=3 {
+            let sender = sender.clone();
+            scheduler
+                .schedule(
+                    uri.clone(),
+                    version,
+                    Duration::from_millis(20),
+                    async move { diagnostic(&format!("version {}", version)) },
+                    move |diagnostics| async move {
+                        sender.send((version, diagnostics)).unwrap();
+                    },
+                )
+                .await;
+        }
+        // A run for an older version than the latest one is ignored
+        let stale_sender = sender.clone();
+        scheduler
+            .schedule(
+                uri.clone(),
+                2,
+                Duration::ZERO,
+                async { diagnostic("stale") },
+                move |diagnostics| async move {
+                    stale_sender.send((2, diagnostics)).unwrap();
+                },
+            )
+            .await;
+        drop(sender);
+
+        let (version, diagnostics) = receiver.recv().await.unwrap();
+        assert_eq!(version, 3);
+        assert_eq!(diagnostics[0].message, "version 3");
+        assert!(receiver.recv().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_in_flight_runs_are_cancelled() {
+        let scheduler = DiagnosticsScheduler::default();
+        let uri = Url::parse("file:///models/customers.sql").unwrap();
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+
+        let slow_sender = sender.clone();
+        scheduler
+            .schedule(
+                uri.clone(),
+                1,
+                Duration::ZERO,
+                async {
+                    tokio::time::sleep(Duration::from_secs(60)).await;
+                    diagnostic("slow")
+                },
+                move |diagnostics| async move {
+                    slow_sender.send(diagnostics).unwrap();
+                },
+            )
+            .await;
+        // Let the first run get past its debounce and start computing
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        scheduler
+            .schedule(
+                uri.clone(),
+                2,
+                Duration::ZERO,
+                async { diagnostic("fast") },
+                move |diagnostics| async move {
+                    sender.send(diagnostics).unwrap();
+                },
+            )
+            .await;
+
+        let diagnostics = tokio::time::timeout(Duration::from_secs(5), receiver.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(diagnostics[0].message, "fast");
+        // The slow run was aborted, so its sender is dropped and the channel closes
+        assert!(receiver.recv().await.is_none());
+
+        scheduler.cancel(&uri).await;
+        assert!(scheduler.runs.lock().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_slow_publish_blocks_only_its_document() {
+        let scheduler = DiagnosticsScheduler::default();
+        let customers = Url::parse("file:///models/customers.sql").unwrap();
+        let orders = Url::parse("file:///models/orders.sql").unwrap();
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+
+        // A client that never reads the diagnostics of the first document
+        scheduler
+            .schedule(
+                customers,
+                1,
+                Duration::ZERO,
+                async { diagnostic("customers") },
+                |_| async { tokio::time::sleep(Duration::from_secs(60)).await },
+            )
+            .await;
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        let orders_run = async {
+            scheduler
+                .schedule(
+                    orders,
+                    1,
+                    Duration::ZERO,
+                    async { diagnostic("orders") },
+                    move |diagnostics| async move {
+                        sender.send(diagnostics).unwrap();
+                    },
+                )
+                .await;
+            receiver.recv().await.unwrap()
+        };
+
+        let diagnostics = tokio::time::timeout(Duration::from_secs(5), orders_run).await.unwrap();
+        assert_eq!(diagnostics[0].message, "orders");
+    }
+}
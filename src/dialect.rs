@@ -0,0 +1,190 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+use serde_yaml::Value;
+use sqlparser::dialect::{
+    BigQueryDialect, DatabricksDialect, DuckDbDialect, PostgreSqlDialect, RedshiftSqlDialect, SnowflakeDialect,
+};
+
+/// The SQL dialect of a warehouse, which decides which constructs the parser accepts and what sqlfluff lints against
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Dialect {
+    #[default]
+    Snowflake,
+    BigQuery,
+    Postgres,
+    Redshift,
+    Databricks,
+    DuckDb,
+}
+
+impl Dialect {
+    /// The dialect of a dbt adapter, by the `type` of a profile output
+    pub fn from_adapter(adapter: &str) -> Option<Dialect> {
+        match adapter.to_lowercase().as_str() {
+            "snowflake" => Some(Dialect::Snowflake),
+            "bigquery" => Some(Dialect::BigQuery),
+            "postgres" => Some(Dialect::Postgres),
+            "redshift" => Some(Dialect::Redshift),
+            "databricks" | "spark" => Some(Dialect::Databricks),
+            "duckdb" => Some(Dialect::DuckDb),
+            _ => None,
+        }
+    }
+
+    /// The name sqlfluff knows the dialect by
+    pub fn sqlfluff_name(self) -> &'static str {
+        match self {
+            Dialect::Snowflake => "snowflake",
+            Dialect::BigQuery => "bigquery",
+            Dialect::Postgres => "postgres",
+            Dialect::Redshift => "redshift",
+            Dialect::Databricks => "databricks",
+            Dialect::DuckDb => "duckdb",
+        }
+    }
+
+    /// The `sqlparser` dialect models are parsed with
+    pub fn parser_dialect(self) -> Box<dyn sqlparser::dialect::Dialect> {
+        match self {
+            Dialect::Snowflake => Box::new(SnowflakeDialect {}),
+            Dialect::BigQuery => Box::new(BigQueryDialect {}),
+            Dialect::Postgres => Box::new(PostgreSqlDialect {}),
+            Dialect::Redshift => Box::new(RedshiftSqlDialect {}),
+            Dialect::Databricks => Box::new(DatabricksDialect {}),
+            Dialect::DuckDb => Box::new(DuckDbDialect {}),
+        }
+    }
+
+    pub fn display_name(self) -> &'static str {
+        match self {
+            Dialect::Snowflake => "Snowflake",
+            Dialect::BigQuery => "BigQuery",
+            Dialect::Postgres => "Postgres",
+            Dialect::Redshift => "Redshift",
+            Dialect::Databricks => "Databricks",
+            Dialect::DuckDb => "DuckDB",
+        }
+    }
+
+    /// The root of the vendor's SQL reference, that the pages of functions and clauses are under. Only the pages of
+    /// Snowflake's are known so far.
+    pub fn sql_reference(self) -> Option<&'static str> {
+        match self {
+            Dialect::Snowflake => Some("https://docs.snowflake.com/en/sql-reference"),
+            _ => None,
+        }
+    }
+
+    /// Whether identifiers are quoted with backticks. BigQuery reads double quotes as strings instead.
+    pub fn backtick_identifiers(self) -> bool {
+        matches!(self, Dialect::BigQuery | Dialect::Databricks)
+    }
+
+    pub fn double_quoted_identifiers(self) -> bool {
+        self != Dialect::BigQuery
+    }
+
+    pub fn qualify(self) -> bool {
+        matches!(self, Dialect::Snowflake | Dialect::BigQuery | Dialect::Databricks | Dialect::DuckDb)
+    }
+
+    /// Whether MINUS can be used for EXCEPT
+    pub fn minus(self) -> bool {
+        matches!(self, Dialect::Snowflake | Dialect::Redshift | Dialect::Databricks)
+    }
+
+    /// Whether `expr::type` casts are supported
+    pub fn double_colon_casts(self) -> bool {
+        self != Dialect::BigQuery
+    }
+
+    /// Whether `select amount::int` names its column `amount`, rather than after the whole expression
+    pub fn cast_keeps_column_name(self) -> bool {
+        matches!(self, Dialect::Postgres | Dialect::Redshift | Dialect::DuckDb)
+    }
+
+    /// The dialect of the dbt project in `root`, from the adapter of the profile it runs with
+    pub fn from_project(root: &Path) -> Option<Dialect> {
+        #[derive(Deserialize)]
+        struct ProjectProfile {
+            profile: Option<String>,
+        }
+
+        let project: ProjectProfile = serde_yaml::from_str(&fs::read_to_string(root.join("dbt_project.yml")).ok()?).ok()?;
+        let profile = project.profile?;
+        profiles_dirs(root)
+            .into_iter()
+            .filter_map(|dir| fs::read_to_string(dir.join("profiles.yml")).ok())
+            .find_map(|profiles| profile_adapter(&profiles, &profile))
+            .and_then(|adapter| Dialect::from_adapter(&adapter))
+    }
+
+    /// The configured dialect, else the one of the dbt project in `root`, else Snowflake
+    pub fn resolve(configured: Option<Dialect>, root: Option<&Path>) -> Dialect {
+        configured
+            .or_else(|| root.and_then(Dialect::from_project))
+            .unwrap_or_default()
+    }
+}
+
+/// Where dbt looks for `profiles.yml`, in order
+fn profiles_dirs(root: &Path) -> Vec<PathBuf> {
+    let mut dirs = vec![root.to_path_buf()];
+    if let Some(dir) = env::var_os("DBT_PROFILES_DIR") {
+        dirs.push(dir.into());
+    }
+    if let Some(home) = env::var_os("HOME").or_else(|| env::var_os("USERPROFILE")) {
+        dirs.push(PathBuf::from(home).join(".dbt"));
+    }
+    dirs
+}
+
+/// The adapter type of the target a profile runs with. The target is often templated (`{{ env_var(...) }}`), in which
+/// case the outputs are assumed to share an adapter and the first one is used.
+fn profile_adapter(profiles: &str, profile: &str) -> Option<String> {
+    let profiles: Value = serde_yaml::from_str(profiles).ok()?;
+    let profile = profiles.get(profile)?;
+    let outputs = profile.get("outputs")?.as_mapping()?;
+    let output = profile
+        .get("target")
+        .and_then(Value::as_str)
+        .and_then(|target| outputs.get(target))
+        .or_else(|| outputs.values().next())?;
+    output.get("type")?.as_str().map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profile_adapter() {
+        let profiles = "
+jaffle_shop:
+  target: prod
+  outputs:
+    dev:
+      type: postgres
+    prod:
+      type: bigquery
+other:
+  target: \"{{ env_var('DBT_TARGET') }}\"
+  outputs:
+    ci:
+      type: duckdb
+";
+        assert_eq!(profile_adapter(profiles, "jaffle_shop"), Some("bigquery".into()));
+        assert_eq!(profile_adapter(profiles, "other"), Some("duckdb".into()));
+        assert_eq!(profile_adapter(profiles, "missing"), None);
+
+        let project = Path::new("./test_sql/jaffa_shop");
+        assert_eq!(Dialect::from_project(project), Some(Dialect::Snowflake));
+        assert_eq!(Dialect::resolve(Some(Dialect::Postgres), Some(project)), Dialect::Postgres);
+        assert_eq!(Dialect::resolve(None, None), Dialect::Snowflake);
+    }
+}
@@ -0,0 +1,223 @@
+use tower_lsp::lsp_types::{DocumentSymbol, SymbolKind};
+
+use crate::{
+    dialect::Dialect,
+    jinja_parser::{JinjaBlockKind, JinjaParser},
+    parser::{self, ColumnSet, Relation},
+    utils::Span,
+};
+
+#[allow(deprecated)]
+fn symbol(
+    name: String,
+    detail: Option<String>,
+    kind: SymbolKind,
+    span: &Span,
+    selection_span: &Span,
+    children: Option<Vec<DocumentSymbol>>,
+) -> DocumentSymbol {
+    DocumentSymbol {
+        name,
+        detail,
+        kind,
+        tags: None,
+        deprecated: None,
+        range: span.to_range(),
+        selection_range: selection_span.to_range(),
+        children,
+    }
+}
+
+fn column_symbols(columns: &ColumnSet) -> Vec<DocumentSymbol> {
+    columns
+        .columns
+        .iter()
+        .map(|column| {
+            symbol(
+                column.name.clone(),
+                None,
+                SymbolKind::FIELD,
+                &column.span,
+                &column.span,
+                None,
+            )
+        })
+        .collect()
+}
+
+/// Symbols for the subqueries a query selects from. Tables are left out, their columns are not known here.
+fn relation_symbols(relations: &[Relation]) -> Vec<DocumentSymbol> {
+    relations
+        .iter()
+        .filter_map(|relation| {
+            let columns = relation.columns.as_ref()?;
+            Some(symbol(
+                relation.name.clone().unwrap_or_else(|| "subquery".into()),
+                Some("subquery".into()),
+                SymbolKind::OBJECT,
+                &relation.span,
+                &relation.span,
+                Some(column_symbols(columns)),
+            ))
+        })
+        .collect()
+}
+
+fn query_symbols(columns: &ColumnSet, relations: &[Relation]) -> Vec<DocumentSymbol> {
+    let mut symbols = column_symbols(columns);
+    symbols.extend(relation_symbols(relations));
+    symbols
+}
+
+/// Builds the outline of a model: config and macro blocks, CTEs with their columns and subqueries, and the final select.
+/// The SQL symbols are left out if the rendered SQL does not parse.
+pub fn document_symbols(src: &str, name: &str, dialect: Dialect) -> Vec<DocumentSymbol> {
+    let mut jinja_parse = JinjaParser::new(src);
+    if jinja_parse.render_jinja().is_err() {
+        return vec![];
+    }
+
+    let mut symbols: Vec<DocumentSymbol> = jinja_parse
+        .blocks()
+        .iter()
+        .map(|block| {
+            let span = Span::from_span(block.span);
+            match &block.kind {
+                JinjaBlockKind::Config => symbol(
+                    "config".into(),
+                    Some(block.span.as_str().trim().to_string()),
+                    SymbolKind::PROPERTY,
+                    &span,
+                    &span,
+                    None,
+                ),
+                JinjaBlockKind::Macro { name, args } => symbol(
+                    name.clone(),
+                    Some(format!("macro({})", args.join(", "))),
+                    SymbolKind::FUNCTION,
+                    &span,
+                    &span,
+                    None,
+                ),
+            }
+        })
+        .collect();
+
+    // Macro files render to no SQL at all
+    if !jinja_parse.output().trim().is_empty() {
+        let model = parser::parse_sql(&jinja_parse, name, dialect).model;
+        for cte in model.ctes.iter().flatten() {
+            symbols.push(symbol(
+                cte.name.clone(),
+                Some("cte".into()),
+                SymbolKind::NAMESPACE,
+                &cte.span,
+                &cte.name_span,
+                Some(query_symbols(&cte.columns, &cte.relations)),
+            ));
+        }
+        let output_name = if model.name.is_empty() {
+            "output".to_string()
+        } else {
+            model.name.clone()
+        };
+        symbols.push(symbol(
+            output_name,
+            Some("model output".into()),
+            SymbolKind::STRUCT,
+            &model.span,
+            &model.span,
+            Some(query_symbols(&model.columns, &model.relations)),
+        ));
+    }
+
+    symbols.sort_by_key(|symbol| (symbol.range.start.line, symbol.range.start.character));
+    symbols
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_document_symbols() {
+        let src = r#"{{ config(materialized='table') }}
+
+with customers as (
+    select id as customer_id, first_name from {{ ref('stg_customers') }}
+)
+
+select customer_id from customers
+"#;
+        let symbols = document_symbols(src, "customers", Dialect::Snowflake);
+        let names: Vec<(&str, SymbolKind)> = symbols
+            .iter()
+            .map(|symbol| (symbol.name.as_str(), symbol.kind))
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                ("config", SymbolKind::PROPERTY),
+                ("customers", SymbolKind::NAMESPACE),
+                ("customers", SymbolKind::STRUCT),
+            ]
+        );
+
+        let cte = &symbols[1];
+        assert_eq!(cte.range.start.line, 2);
+        assert_eq!(cte.selection_range.start.character, 5);
+        let columns: Vec<&str> = cte
+            .children
+            .iter()
+            .flatten()
+            .map(|column| column.name.as_str())
+            .collect();
+        assert_eq!(columns, vec!["customer_id", "first_name"]);
+        assert_eq!(symbols[2].range.start.line, 6);
+    }
+
+    #[test]
+    fn test_subquery_symbols() {
+        let src = "select o.id, t.total\nfrom orders o\njoin (select order_id, sum(amount) as total from payments group by 1) t using (order_id)\n";
+        let symbols = document_symbols(src, "orders", Dialect::Snowflake);
+        assert_eq!(symbols.len(), 1);
+        let children = symbols[0].children.as_ref().unwrap();
+        let names: Vec<(&str, SymbolKind)> = children
+            .iter()
+            .map(|symbol| (symbol.name.as_str(), symbol.kind))
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                ("id", SymbolKind::FIELD),
+                ("total", SymbolKind::FIELD),
+                ("t", SymbolKind::OBJECT),
+            ]
+        );
+        let columns: Vec<&str> = children[2]
+            .children
+            .iter()
+            .flatten()
+            .map(|column| column.name.as_str())
+            .collect();
+        assert_eq!(columns, vec!["order_id", "total"]);
+        assert_eq!(children[2].range.start.line, 2);
+    }
+
+    #[test]
+    fn test_macro_symbols() {
+        let src = r#"{% macro cents_to_dollars(column_name, precision=2) -%}
+    ({{ column_name }} / 100)::numeric(16, {{ precision }})
+{%- endmacro %}
+"#;
+        let symbols = document_symbols(src, "cents_to_dollars", Dialect::Snowflake);
+        assert_eq!(symbols.len(), 1);
+        assert_eq!(symbols[0].name, "cents_to_dollars");
+        assert_eq!(symbols[0].kind, SymbolKind::FUNCTION);
+        assert_eq!(
+            symbols[0].detail.as_deref(),
+            Some("macro(column_name, precision)")
+        );
+        assert_eq!(symbols[0].range.end.line, 2);
+    }
+}
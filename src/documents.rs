@@ -0,0 +1,144 @@
+use std::collections::HashMap;
+
+use ropey::Rope;
+use tower_lsp::lsp_types::{Position, TextDocumentContentChangeEvent, Url};
+
+/// An open text document and the version the client last sent
+pub struct Document {
+    pub rope: Rope,
+    pub version: i32,
+}
+
+/// Converts an LSP position, whose character is counted in UTF-16 code units, to a char index in the rope.
+/// Positions past the end of a line are clamped to the end of that line, and positions past the last line to the end of the rope.
+pub fn position_to_char(rope: &Rope, position: Position) -> usize {
+    let line = position.line as usize;
+    if line >= rope.len_lines() {
+        return rope.len_chars();
+    }
+    let line_start = rope.line_to_char(line);
+    let line_slice = rope.line(line);
+    let mut line_length = line_slice.len_chars();
+    // The position can not point past the line break
+    while line_length > 0 && matches!(line_slice.char(line_length - 1), '\n' | '\r') {
+        line_length -= 1;
+    }
+
+    let line_start_utf16 = rope.char_to_utf16_cu(line_start);
+    let line_end_utf16 = rope.char_to_utf16_cu(line_start + line_length);
+    let utf16 = (line_start_utf16 + position.character as usize).min(line_end_utf16);
+    rope.utf16_cu_to_char(utf16)
+}
+
+/// The documents the client has open, keyed by URI
+#[derive(Default)]
+pub struct DocumentStore {
+    documents: HashMap<Url, Document>,
+}
+
+impl DocumentStore {
+    pub fn open(&mut self, uri: Url, text: &str, version: i32) {
+        self.documents.insert(
+            uri,
+            Document {
+                rope: Rope::from_str(text),
+                version,
+            },
+        );
+    }
+
+    /// Applies the changes of a `didChange` notification in order. A change without a range replaces the whole document.
+    /// Returns `None` if the document is not open.
+    pub fn apply_changes(
+        &mut self,
+        uri: &Url,
+        version: i32,
+        changes: Vec<TextDocumentContentChangeEvent>,
+    ) -> Option<&Document> {
+        let document = self.documents.get_mut(uri)?;
+        for change in changes {
+            match change.range {
+                Some(range) => {
+                    let start = position_to_char(&document.rope, range.start);
+                    let end = position_to_char(&document.rope, range.end).max(start);
+                    document.rope.remove(start..end);
+                    document.rope.insert(start, &change.text);
+                }
+                None => document.rope = Rope::from_str(&change.text),
+            }
+        }
+        document.version = version;
+        Some(document)
+    }
+
+    pub fn close(&mut self, uri: &Url) {
+        self.documents.remove(uri);
+    }
+
+    pub fn get(&self, uri: &Url) -> Option<&Document> {
+        self.documents.get(uri)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tower_lsp::lsp_types::Range;
+
+    fn change(start: (u32, u32), end: (u32, u32), text: &str) -> TextDocumentContentChangeEvent {
+        TextDocumentContentChangeEvent {
+            range: Some(Range {
+                start: Position {
+                    line: start.0,
+                    character: start.1,
+                },
+                end: Position {
+                    line: end.0,
+                    character: end.1,
+                },
+            }),
+            range_length: None,
+            text: text.into(),
+        }
+    }
+
+    #[test]
+    fn test_incremental_changes() {
+        let uri = Url::parse("file:///models/customers.sql").unwrap();
+        let mut store = DocumentStore::default();
+        store.open(uri.clone(), "select '🦀' as crab,\n    id\nfrom customers\n", 1);
+
+        // The crab is two UTF-16 code units, so "as" starts at character 12 rather than 11
+        let changes = vec![
+            change((0, 12), (0, 20), "as rust,"),
+            change((1, 4), (1, 6), "customer_id"),
+            change((2, 5), (2, 14), "{{ ref('stg_customers') }}"),
+        ];
+        let document = store.apply_changes(&uri, 2, changes).unwrap();
+        assert_eq!(
+            document.rope.to_string(),
+            "select '🦀' as rust,\n    customer_id\nfrom {{ ref('stg_customers') }}\n"
+        );
+        assert_eq!(document.version, 2);
+
+        // Positions past the end of a line stay on that line
+        let changes = vec![change((0, 100), (1, 0), " "), change((5, 0), (5, 0), "-- end")];
+        let document = store.apply_changes(&uri, 3, changes).unwrap();
+        assert_eq!(
+            document.rope.to_string(),
+            "select '🦀' as rust,     customer_id\nfrom {{ ref('stg_customers') }}\n-- end"
+        );
+
+        let full = TextDocumentContentChangeEvent {
+            range: None,
+            range_length: None,
+            text: "select 1".into(),
+        };
+        let document = store.apply_changes(&uri, 4, vec![full]).unwrap();
+        assert_eq!(document.rope.to_string(), "select 1");
+
+        store.close(&uri);
+        assert!(store.get(&uri).is_none());
+        assert!(store.apply_changes(&uri, 5, vec![]).is_none());
+    }
+}
@@ -0,0 +1,77 @@
+use std::path::Path;
+
+use tower_lsp::lsp_types::{Range, TextEdit};
+
+use crate::{code_actions::template_edits, jinja_parser::JinjaParser, sqlfluff::Sqlfluff};
+
+/// The formatting changes that can be made in the template, limited to those within `range` if given.
+/// SQL generated by Jinja is left as it is, and so are the Jinja expressions themselves.
+fn formatting_edits(jinja_parse: &JinjaParser, formatted: &str, range: Option<Range>) -> Vec<TextEdit> {
+    template_edits(jinja_parse, formatted)
+        .into_iter()
+        .filter(|edit| !edit.generated)
+        .map(|edit| edit.edit)
+        .filter(|edit| {
+            range.is_none_or(|range| range.start <= edit.range.start && edit.range.end <= range.end)
+        })
+        .collect()
+}
+
+/// Formats the template by running `sqlfluff fix` with the formatting `rules` on the rendered SQL.
+/// Returns `None` if the template can not be rendered or sqlfluff fails.
+pub async fn format(
+    src: &str,
+    path: Option<&Path>,
+    range: Option<Range>,
+    rules: &str,
+    sqlfluff: &Sqlfluff,
+) -> Option<Vec<TextEdit>> {
+    let mut jinja_parse = JinjaParser::new(src);
+    jinja_parse.render_jinja().ok()?;
+    if jinja_parse.output().trim().is_empty() {
+        return Some(vec![]);
+    }
+    let formatted = sqlfluff
+        .fix(jinja_parse.output(), path, Some(rules))
+        .await
+        .ok()?;
+    Some(formatting_edits(&jinja_parse, &formatted, range))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tower_lsp::lsp_types::Position;
+
+    #[test]
+    fn test_formatting_edits() {
+        let src = "select a,b\nfrom {{ ref('orders') }}\nwhere a>1\n";
+        let mut jinja_parse = JinjaParser::new(src);
+        jinja_parse.render_jinja().unwrap();
+        let formatted = "SELECT\n    a,\n    b\nFROM ORDERS\nWHERE a > 1\n";
+
+        let edits = formatting_edits(&jinja_parse, formatted, None);
+        let mut formatted_src = src.to_string();
+        // Applied back to front so the earlier ranges stay valid. Every edit here is on a single line.
+        for edit in edits.iter().rev() {
+            let line_start: usize = formatted_src
+                .split_inclusive('\n')
+                .take(edit.range.start.line as usize)
+                .map(str::len)
+                .sum();
+            let start = line_start + edit.range.start.character as usize;
+            let end = line_start + edit.range.end.character as usize;
+            formatted_src.replace_range(start..end, &edit.new_text);
+        }
+        assert_eq!(
+            formatted_src,
+            "SELECT\n    a,\n    b\nFROM {{ ref('orders') }}\nWHERE a > 1\n"
+        );
+
+        // Only the last line is in the range
+        let range = Range::new(Position::new(2, 0), Position::new(3, 0));
+        let edits = formatting_edits(&jinja_parse, formatted, Some(range));
+        assert!(edits.iter().all(|edit| edit.range.start.line == 2));
+        assert!(!edits.is_empty());
+    }
+}
@@ -0,0 +1,58 @@
+//! The format of the function catalogs in `catalog/`. The scraper writes them and the build script reads them, so
+//! this is also compiled into the build script and depends on nothing else in the crate.
+
+use serde::{Deserialize, Serialize};
+
+/// The version of the format, bumped when the build script can no longer read older catalogs
+pub const CATALOG_VERSION: u32 = 1;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Catalog {
+    pub version: u32,
+    pub functions: Vec<CatalogFunction>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CatalogFunction {
+    /// The name, in uppercase
+    pub name: String,
+    /// The category of the documentation the function is listed under, like `aggregate` or `string`
+    pub category: String,
+    pub summary: String,
+    /// The data type the function returns, when the documentation names a single one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub returns: Option<String>,
+    pub signatures: Vec<CatalogSignature>,
+    /// The output columns of a table function
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub columns: Vec<String>,
+}
+
+/// One way to call a function
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CatalogSignature {
+    /// The syntax line of the documentation, on a single line
+    pub syntax: String,
+    pub arguments: Vec<CatalogArgument>,
+    /// Whether the arguments can be preceded by DISTINCT
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub distinct: bool,
+    /// Whether the call takes an OVER clause
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub window: bool,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CatalogArgument {
+    pub name: String,
+    /// Whether the argument is in brackets, so it can be left out
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub optional: bool,
+    /// Whether the argument can be repeated, like the expressions of `COALESCE`
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub variadic: bool,
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
@@ -0,0 +1,209 @@
+//! The built-in functions of each warehouse, generated by the build script from the catalogs in `catalog/`. A catalog
+//! need not list every function of its warehouse, so a name missing from it is not necessarily wrong.
+
+use crate::dialect::Dialect;
+
+pub mod catalog;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FunctionKind {
+    Scalar,
+    Aggregate,
+    Window,
+    /// A function that returns rows, used in FROM like FLATTEN
+    Table,
+}
+
+#[derive(Debug)]
+pub struct Argument {
+    pub name: &'static str,
+    pub optional: bool,
+    pub variadic: bool,
+}
+
+/// One way to call a function, labelled with its syntax line from the documentation
+#[derive(Debug)]
+pub struct Signature {
+    pub label: &'static str,
+    pub arguments: &'static [Argument],
+}
+
+#[derive(Debug)]
+pub struct Function {
+    /// The name, in uppercase
+    pub name: &'static str,
+    pub kind: FunctionKind,
+    pub summary: &'static str,
+    /// The data type the function returns, if the documentation names one
+    pub returns: Option<&'static str>,
+    pub signatures: &'static [Signature],
+    /// The output columns of a table function
+    pub columns: &'static [&'static str],
+}
+
+impl Signature {
+    /// Whether the signature can be called with `count` arguments
+    pub fn accepts(&self, count: usize) -> bool {
+        let required = self.arguments.iter().filter(|argument| !argument.optional).count();
+        let variadic = self.arguments.iter().any(|argument| argument.variadic);
+        count >= required && (variadic || count <= self.arguments.len())
+    }
+}
+
+impl Function {
+    /// How many arguments the signatures take together, like `1 to 3 arguments` or `at least 2 arguments`
+    pub fn arity(&self) -> String {
+        let mut ranges: Vec<(usize, Option<usize>)> = self
+            .signatures
+            .iter()
+            .map(|signature| {
+                let required = signature.arguments.iter().filter(|argument| !argument.optional).count();
+                let variadic = signature.arguments.iter().any(|argument| argument.variadic);
+                (required, (!variadic).then_some(signature.arguments.len()))
+            })
+            .collect();
+        ranges.sort();
+        // Signatures that take overlapping or adjacent counts are described together
+        let mut merged: Vec<(usize, Option<usize>)> = vec![];
+        for (min, max) in ranges {
+            let overlaps = |(_, last): &&mut (usize, Option<usize>)| last.is_none_or(|last| min <= last + 1);
+            match merged.last_mut().filter(overlaps) {
+                Some(range) => {
+                    range.1 = match (range.1, max) {
+                        (Some(last), Some(max)) => Some(last.max(max)),
+                        _ => None,
+                    }
+                }
+                None => merged.push((min, max)),
+            }
+        }
+        let counts: Vec<String> = merged
+            .iter()
+            .map(|range| match *range {
+                (min, None) => format!("at least {}", min),
+                (min, Some(max)) if min == max => min.to_string(),
+                (min, Some(max)) => format!("{} to {}", min, max),
+            })
+            .collect();
+        match counts.join(" or ").as_str() {
+            "0" => "no arguments".into(),
+            "1" => "1 argument".into(),
+            counts => format!("{} arguments", counts),
+        }
+    }
+}
+
+include!(concat!(env!("OUT_DIR"), "/snowflake_functions.rs"));
+
+/// The functions of a dialect, sorted by name. Only Snowflake's have been cataloged so far.
+pub fn catalog(dialect: Dialect) -> &'static [Function] {
+    match dialect {
+        Dialect::Snowflake => SNOWFLAKE,
+        _ => &[],
+    }
+}
+
+/// A function by its name, in any case
+pub fn lookup(dialect: Dialect, name: &str) -> Option<&'static Function> {
+    let functions = catalog(dialect);
+    let name = name.to_uppercase();
+    functions
+        .binary_search_by(|function| function.name.cmp(name.as_str()))
+        .ok()
+        .map(|i| &functions[i])
+}
+
+/// The number of insertions, deletions, substitutions and transpositions of adjacent characters that turn `a` into `b`
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut distances = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in distances.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, distance) in distances[0].iter_mut().enumerate() {
+        *distance = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let substitution = distances[i - 1][j - 1] + usize::from(a[i - 1] != b[j - 1]);
+            let mut distance = substitution.min(distances[i - 1][j] + 1).min(distances[i][j - 1] + 1);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(distances[i - 2][j - 2] + 1);
+            }
+            distances[i][j] = distance;
+        }
+    }
+    distances[a.len()][b.len()]
+}
+
+/// The function a name that is not in the catalog is probably a typo of: one a single edit away. Short names are too
+/// close to other functions to tell, and a name further away can be a user-defined function or one the catalog lacks.
+pub fn suggest(dialect: Dialect, name: &str) -> Option<&'static Function> {
+    let name = name.to_uppercase();
+    if name.chars().count() < 4 {
+        return None;
+    }
+    catalog(dialect)
+        .iter()
+        .find(|function| edit_distance(&name, function.name) == 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lookup() {
+        let flatten = lookup(Dialect::Snowflake, "flatten").unwrap();
+        assert_eq!(flatten.kind, FunctionKind::Table);
+        assert_eq!(flatten.columns, ["SEQ", "KEY", "PATH", "INDEX", "VALUE", "THIS"]);
+
+        let dateadd = lookup(Dialect::Snowflake, "DateAdd").unwrap();
+        let arguments: Vec<&str> = dateadd.signatures[0].arguments.iter().map(|a| a.name).collect();
+        assert_eq!(arguments, vec!["date_or_time_part", "value", "date_or_time_expr"]);
+
+        assert_eq!(lookup(Dialect::Snowflake, "sum").unwrap().kind, FunctionKind::Aggregate);
+        assert!(lookup(Dialect::Snowflake, "not_a_function").is_none());
+        assert!(lookup(Dialect::Postgres, "flatten").is_none());
+    }
+
+    #[test]
+    fn test_arity() {
+        let arity = |name: &str| lookup(Dialect::Snowflake, name).unwrap().arity();
+        assert_eq!(arity("dateadd"), "3 arguments");
+        assert_eq!(arity("round"), "1 to 3 arguments");
+        assert_eq!(arity("coalesce"), "at least 2 arguments");
+        assert_eq!(arity("upper"), "1 argument");
+        assert_eq!(arity("current_date"), "no arguments");
+        assert_eq!(arity("to_date"), "1 to 2 arguments");
+
+        let dateadd = &lookup(Dialect::Snowflake, "dateadd").unwrap().signatures[0];
+        assert!(dateadd.accepts(3));
+        assert!(!dateadd.accepts(2) && !dateadd.accepts(4));
+        let coalesce = &lookup(Dialect::Snowflake, "coalesce").unwrap().signatures[0];
+        assert!(coalesce.accepts(5) && !coalesce.accepts(1));
+    }
+
+    #[test]
+    fn test_suggest() {
+        let suggestion = |name: &str| suggest(Dialect::Snowflake, name).map(|function| function.name);
+        assert_eq!(suggestion("dateaad"), Some("DATEADD"));
+        assert_eq!(suggestion("dtaeadd"), Some("DATEADD"));
+        assert_eq!(suggestion("coalese"), Some("COALESCE"));
+        assert_eq!(suggestion("zeroifnulll"), Some("ZEROIFNULL"));
+        assert_eq!(suggestion("my_udf"), None);
+        assert_eq!(suggestion("dateadd_days"), None);
+        assert_eq!(suggestion("sun"), None);
+        assert!(suggest(Dialect::Postgres, "dateaad").is_none());
+    }
+
+    #[test]
+    fn test_catalog() {
+        let functions = catalog(Dialect::Snowflake);
+        assert!(functions.windows(2).all(|pair| pair[0].name < pair[1].name));
+        for function in functions {
+            assert!(!function.signatures.is_empty(), "{} has no signature", function.name);
+        }
+    }
+}
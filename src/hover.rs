@@ -0,0 +1,292 @@
+use sqlparser::tokenizer::{Token, TokenWithSpan, Tokenizer};
+use tower_lsp::lsp_types::{Hover, HoverContents, MarkupContent, MarkupKind};
+
+use crate::{
+    dialect::Dialect,
+    functions::{self, Function},
+    jinja_parser::JinjaParser,
+    parser::Rendered,
+    utils::Span,
+};
+
+/// A clause of a query, documented on a page of the SQL reference
+struct Clause {
+    /// The keyword that starts the clause. Hovering the BY of a two word clause shows it too.
+    keyword: &'static str,
+    syntax: &'static str,
+    summary: &'static str,
+    /// The page under the dialect's SQL reference
+    page: &'static str,
+    supported: fn(Dialect) -> bool,
+}
+
+const CLAUSES: [Clause; 14] = [
+    Clause {
+        keyword: "WITH",
+        syntax: "WITH <cte_name> AS ( <query> ) [ , ... ] <query>",
+        summary: "Defines common table expressions that the rest of the query can select from by name.",
+        page: "constructs/with",
+        supported: |_| true,
+    },
+    Clause {
+        keyword: "SELECT",
+        syntax: "SELECT [ DISTINCT ] <expr> [ [ AS ] <alias> ] [ , ... ]",
+        summary: "Lists the columns of the result, as expressions over the relations the query selects from.",
+        page: "sql/select",
+        supported: |_| true,
+    },
+    Clause {
+        keyword: "FROM",
+        syntax: "FROM <relation> [ [ AS ] <alias> ] [ , ... ]",
+        summary: "Names the tables, subqueries and table functions the query selects from.",
+        page: "constructs/from",
+        supported: |_| true,
+    },
+    Clause {
+        keyword: "JOIN",
+        syntax: "<relation> [ { INNER | { LEFT | RIGHT | FULL } [ OUTER ] | CROSS } ] JOIN <relation> [ ON <condition> | USING ( <column> [ , ... ] ) ]",
+        summary: "Combines the rows of two relations, pairing the rows that match the join condition.",
+        page: "constructs/join",
+        supported: |_| true,
+    },
+    Clause {
+        keyword: "WHERE",
+        syntax: "WHERE <predicate>",
+        summary: "Filters the rows of the relations before they are grouped.",
+        page: "constructs/where",
+        supported: |_| true,
+    },
+    Clause {
+        keyword: "GROUP",
+        syntax: "GROUP BY <expr> [ , ... ]",
+        summary: "Groups the rows with the same values of the expressions, so aggregate functions compute one value per group.",
+        page: "constructs/group-by",
+        supported: |_| true,
+    },
+    Clause {
+        keyword: "HAVING",
+        syntax: "HAVING <predicate>",
+        summary: "Filters the groups of a GROUP BY, and can use aggregate functions.",
+        page: "constructs/having",
+        supported: |_| true,
+    },
+    Clause {
+        keyword: "QUALIFY",
+        syntax: "QUALIFY <predicate>",
+        summary: "Filters the rows after window functions are computed, as HAVING does for aggregate functions.",
+        page: "constructs/qualify",
+        supported: Dialect::qualify,
+    },
+    Clause {
+        keyword: "ORDER",
+        syntax: "ORDER BY <expr> [ ASC | DESC ] [ NULLS { FIRST | LAST } ] [ , ... ]",
+        summary: "Sorts the rows of the result, or of a window partition.",
+        page: "constructs/order-by",
+        supported: |_| true,
+    },
+    Clause {
+        keyword: "PARTITION",
+        syntax: "OVER ( [ PARTITION BY <expr> [ , ... ] ] [ ORDER BY <expr> [ , ... ] ] [ <window_frame> ] )",
+        summary: "Divides the rows into the partitions a window function is computed over.",
+        page: "functions-window-syntax",
+        supported: |_| true,
+    },
+    Clause {
+        keyword: "LIMIT",
+        syntax: "LIMIT <count> [ OFFSET <start> ]",
+        summary: "Returns at most the given number of rows of the result.",
+        page: "constructs/limit",
+        supported: |_| true,
+    },
+    Clause {
+        keyword: "UNION",
+        syntax: "<query> UNION [ ALL ] [ BY NAME ] <query>",
+        summary: "Combines the rows of two queries. Without ALL, duplicate rows are removed.",
+        page: "operators-query",
+        supported: |_| true,
+    },
+    Clause {
+        keyword: "EXCEPT",
+        syntax: "<query> EXCEPT <query>",
+        summary: "Returns the rows of the first query that the second query does not return.",
+        page: "operators-query",
+        supported: |_| true,
+    },
+    Clause {
+        keyword: "MINUS",
+        syntax: "<query> MINUS <query>",
+        summary: "Returns the rows of the first query that the second query does not return, like EXCEPT.",
+        page: "operators-query",
+        supported: Dialect::minus,
+    },
+];
+
+fn markdown(syntax: &[&str], summary: &str, link: Option<(String, &str)>) -> MarkupContent {
+    let mut value = format!("```sql\n{}\n```\n\n{}", syntax.join("\n"), summary);
+    if let Some((url, dialect)) = link {
+        value.push_str(&format!("\n\n[{} documentation]({})", dialect, url));
+    }
+    MarkupContent {
+        kind: MarkupKind::Markdown,
+        value,
+    }
+}
+
+fn function_hover(function: &Function, dialect: Dialect) -> MarkupContent {
+    let syntax: Vec<&str> = function.signatures.iter().map(|signature| signature.label).collect();
+    let link = dialect.sql_reference().map(|reference| {
+        (
+            format!("{}/functions/{}", reference, function.name.to_lowercase()),
+            dialect.display_name(),
+        )
+    });
+    markdown(&syntax, function.summary, link)
+}
+
+fn clause_hover(clause: &Clause, dialect: Dialect) -> MarkupContent {
+    let link = dialect
+        .sql_reference()
+        .map(|reference| (format!("{}/{}", reference, clause.page), dialect.display_name()));
+    markdown(&[clause.syntax], clause.summary, link)
+}
+
+/// The word token at `offset` in the rendered SQL, with the tokens around it
+fn word_at<'t>(
+    tokens: &'t [TokenWithSpan],
+    rendered: &Rendered,
+    offset: usize,
+) -> Option<(&'t TokenWithSpan, Option<&'t Token>, Option<&'t Token>)> {
+    let tokens: Vec<&TokenWithSpan> = tokens
+        .iter()
+        .filter(|token| !matches!(token.token, Token::Whitespace(_)))
+        .collect();
+    let i = tokens.iter().position(|token| {
+        matches!(&token.token, Token::Word(word) if word.quote_style.is_none())
+            && rendered.offset(token.span.start).is_some_and(|start| start <= offset)
+            && rendered.offset(token.span.end).is_some_and(|end| offset <= end)
+    })?;
+    let previous = i.checked_sub(1).map(|i| &tokens[i].token);
+    let next = tokens.get(i + 1).map(|token| &token.token);
+    Some((tokens[i], previous, next))
+}
+
+/// The documentation of the function or clause keyword at `offset`, a byte offset in the source of the model
+pub fn hover(src: &str, offset: usize, dialect: Dialect) -> Option<Hover> {
+    let mut jinja_parse = JinjaParser::new(src);
+    jinja_parse.render_jinja().ok()?;
+    let offset = jinja_parse.translate_to_output(offset)?;
+    let parser_dialect = dialect.parser_dialect();
+    let tokens = Tokenizer::new(parser_dialect.as_ref(), jinja_parse.output())
+        .tokenize_with_location()
+        .ok()?;
+    let rendered = Rendered::new(&jinja_parse);
+    let (token, previous, next) = word_at(&tokens, &rendered, offset)?;
+    let Token::Word(word) = &token.token else {
+        return None;
+    };
+    let name = word.value.to_uppercase();
+
+    let function = functions::lookup(dialect, &name)
+        // Context functions like CURRENT_DATE can be called without parentheses
+        .filter(|_| next == Some(&Token::LParen) || name.starts_with("CURRENT_"));
+    let contents = match function {
+        Some(function) => function_hover(function, dialect),
+        None => {
+            let keyword = match previous {
+                Some(Token::Word(previous)) if name == "BY" => previous.value.to_uppercase(),
+                _ => name,
+            };
+            let clause = CLAUSES
+                .iter()
+                .find(|clause| clause.keyword == keyword && (clause.supported)(dialect))?;
+            clause_hover(clause, dialect)
+        }
+    };
+    Some(Hover {
+        contents: HoverContents::Markup(contents),
+        range: rendered
+            .pest_span(token.span)
+            .map(|span| Span::from_span(span).to_range()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use tower_lsp::lsp_types::{Position, Range};
+
+    use super::*;
+
+    /// The hover at the `|` in the source, and the range it is for
+    fn hover_at(src: &str, dialect: Dialect) -> Option<(String, Option<Range>)> {
+        let offset = src.find('|').unwrap();
+        let src = src.replacen('|', "", 1);
+        let hover = hover(&src, offset, dialect)?;
+        match hover.contents {
+            HoverContents::Markup(markup) => Some((markup.value, hover.range)),
+            _ => None,
+        }
+    }
+
+    fn range(line: u32, start: u32, end: u32) -> Option<Range> {
+        Some(Range {
+            start: Position { line, character: start },
+            end: Position { line, character: end },
+        })
+    }
+
+    #[test]
+    fn test_function_hover() {
+        let (value, range) = hover_at("select zero|ifnull(amount) from orders", Dialect::Snowflake).unwrap();
+        assert_eq!(
+            value,
+            "```sql\nZEROIFNULL( <expr> )\n```\n\nReturns 0 if its argument is null; otherwise, returns its argument.\n\n\
+             [Snowflake documentation](https://docs.snowflake.com/en/sql-reference/functions/zeroifnull)"
+        );
+        assert_eq!(range, self::range(0, 7, 17));
+
+        // Every signature is shown
+        let (value, _) = hover_at("select |to_date(x) from orders", Dialect::Snowflake).unwrap();
+        assert!(value.starts_with("```sql\nTO_DATE( <string_expr> [, <format> ] )\nTO_DATE( <timestamp_expr> )\n"));
+
+        assert!(hover_at("select current_date| as today", Dialect::Snowflake).is_some());
+        // A column that happens to share a function's name is not a call
+        assert!(hover_at("select mode| from orders", Dialect::Snowflake).is_none());
+        assert!(hover_at("select \"ZEROIFNULL\"|(amount) from orders", Dialect::Snowflake).is_none());
+        assert!(hover_at("select my_udf|(amount) from orders", Dialect::Snowflake).is_none());
+        assert!(hover_at("select zeroifnull|(amount) from orders", Dialect::BigQuery).is_none());
+    }
+
+    #[test]
+    fn test_clause_hover() {
+        let src = "select customer_id, row_number() over (partition by customer_id order by ts) as rn\nfrom orders\nqu|alify rn = 1";
+        let (value, range) = hover_at(src, Dialect::Snowflake).unwrap();
+        assert!(value.starts_with("```sql\nQUALIFY <predicate>\n```\n\nFilters the rows after window functions"));
+        assert!(value.ends_with("(https://docs.snowflake.com/en/sql-reference/constructs/qualify)"));
+        assert_eq!(range, self::range(2, 0, 7));
+
+        let (value, _) = hover_at("select a, count(*) from t group b|y a", Dialect::Snowflake).unwrap();
+        assert!(value.contains("GROUP BY <expr>"));
+        let (value, _) = hover_at("select a from t un|ion all select b from u", Dialect::Snowflake).unwrap();
+        assert!(value.contains("UNION [ ALL ]"));
+
+        // Dialects without QUALIFY or MINUS, and without a known SQL reference
+        let (value, _) = hover_at("select a from t wh|ere a > 1", Dialect::Postgres).unwrap();
+        assert!(value.ends_with("Filters the rows of the relations before they are grouped."));
+        assert!(hover_at("select a from t qualify| a = 1", Dialect::Postgres).is_none());
+        assert!(hover_at("select a from t min|us select b from u", Dialect::Postgres).is_none());
+        assert!(hover_at("select a from t min|us select b from u", Dialect::Snowflake).is_some());
+        assert!(hover_at("select a, b fr|om t", Dialect::Snowflake).is_some());
+        assert!(hover_at("select a fr|om", Dialect::Snowflake).is_some());
+        assert!(hover_at("select a|, b from t", Dialect::Snowflake).is_none());
+    }
+
+    #[test]
+    fn test_hover_with_jinja() {
+        let src = "{{ config(materialized='table') }}\nselect iff(a, 1, 0) from {{ ref('orders') }}\nwhere nvl|(b, 0) > 0";
+        let (value, range) = hover_at(src, Dialect::Snowflake).unwrap();
+        assert!(value.starts_with("```sql\nNVL( <expr1> , <expr2> )\n```"));
+        assert_eq!(range, self::range(2, 6, 9));
+        // Inside a Jinja expression there is no SQL
+        assert!(hover_at("select a from {{ re|f('orders') }}", Dialect::Snowflake).is_none());
+    }
+}
@@ -22,12 +22,40 @@ struct TemplateOutput<'i> {
     section_type: SectionType,
 }
 
+impl TemplateOutput<'_> {
+    /// The part of the output a Jinja expression generates. Its output is padded with a space on either side, so it
+    /// stays apart from the SQL around it, and that padding is not generated by the template.
+    fn generated(&self) -> (usize, usize) {
+        let (start, end) = self.out_span;
+        if start == end {
+            (start, end)
+        } else {
+            (start + 1, end - 1)
+        }
+    }
+}
+
+/// The kinds of Jinja constructs that are kept around after rendering
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum JinjaBlockKind {
+    Config,
+    Macro { name: String, args: Vec<String> },
+}
+
+/// A Jinja construct and its span in the source file
+#[derive(Debug)]
+pub struct JinjaBlock<'i> {
+    pub kind: JinjaBlockKind,
+    pub span: pest::Span<'i>,
+}
+
 #[derive(Parser)]
 #[grammar = "jinja.pest"]
 struct JinjaParserPest;
 
 pub struct JinjaParser<'i> {
     snippets: Option<Vec<TemplateOutput<'i>>>,
+    blocks: Vec<JinjaBlock<'i>>,
     out_string: String,
     src: &'i str,
 }
@@ -69,10 +97,16 @@ impl<'i> JinjaParser<'i> {
                     return Err("Unknown Jinja".to_string())
                 }
 
-                for pair in pairs {
+                for pair in pairs.flat_map(|output| output.into_inner()) {
                     let pair_in_span = pair.as_span();
                     let start = cur_length;
                     let rule = pair.as_rule();
+                    if let Some(kind) = jinja_block(pair.clone()) {
+                        self.blocks.push(JinjaBlock {
+                            kind,
+                            span: pair_in_span,
+                        });
+                    }
                     if let Some(s) = parse_pair(pair) {
                         let end = start + s.len();
                         cur_length = end;
@@ -81,7 +115,9 @@ impl<'i> JinjaParser<'i> {
                             pair_in_span,
                             (start, end),
                             match rule {
-                                Rule::expr_template => SectionType::Jinja,
+                                Rule::expr_template | Rule::macro_template | Rule::comment_template => {
+                                    SectionType::Jinja
+                                }
                                 Rule::not_jinja => SectionType::Sql,
                                 _ => {
                                     return Err(format!("Unexpected rule: {:?}", rule));
@@ -98,19 +134,20 @@ impl<'i> JinjaParser<'i> {
         let mut out_string = String::new();
         pre_snippets
             .iter()
-            .for_each(|(s, _, _, _)| out_string.push_str(&s));
+            .for_each(|(s, _, _, _)| out_string.push_str(s));
         self.out_string = out_string;
         let pre_snippets: Vec<(pest::Span, (usize, usize), SectionType)> = pre_snippets
             .into_iter()
             .map(|(_, pair_in_span, out_span, section_type)| (pair_in_span, out_span, section_type))
             .collect();
         self.set_snippets(pre_snippets);
-        return Ok(());
+        Ok(())
     }
 
     pub fn new(src: &'i str) -> Self {
         Self {
             snippets: None,
+            blocks: vec![],
             out_string: String::new(),
             src,
         }
@@ -120,23 +157,28 @@ impl<'i> JinjaParser<'i> {
         &self.out_string
     }
 
-    pub fn source(&self) -> &str {
-        &self.src
+    /// The config and macro blocks found while rendering, in source order
+    pub fn blocks(&self) -> &[JinjaBlock<'i>] {
+        &self.blocks
     }
 
-    pub fn translate(&self, out_position: usize) -> Option<pest::Position> {
+    pub fn translate(&self, out_position: usize) -> Option<pest::Position<'i>> {
         if let Some(snippets) = &self.snippets {
             for snippet in snippets {
-                if out_position > snippet.out_span.0 && out_position < snippet.out_span.1 {
+                if out_position >= snippet.out_span.0 && out_position < snippet.out_span.1 {
                     match snippet.section_type {
+                        // The padding after the output is past the end of the expression
+                        SectionType::Jinja if out_position >= snippet.generated().1 => {
+                            return pest::Position::new(self.src, snippet.in_span.end());
+                        }
                         SectionType::Jinja => {
                             let in_position = snippet.in_span.start();
-                            return pest::Position::new(&self.src, in_position);
+                            return pest::Position::new(self.src, in_position);
                         }
                         SectionType::Sql => {
                             let in_position =
                                 snippet.in_span.start() + (out_position - snippet.out_span.0);
-                            return pest::Position::new(&self.src, in_position);
+                            return pest::Position::new(self.src, in_position);
                         }
                     }
                 }
@@ -146,20 +188,120 @@ impl<'i> JinjaParser<'i> {
             None
         }
     }
+
+    /// Like `translate`, but for exclusive end positions. An end inside Jinja output maps to the end of the Jinja
+    /// expression, unless it is in the padding before the output.
+    fn translate_end(&self, out_position: usize) -> Option<pest::Position<'i>> {
+        let snippets = self.snippets.as_ref()?;
+        for snippet in snippets {
+            if out_position > snippet.out_span.0 && out_position <= snippet.out_span.1 {
+                let in_position = match snippet.section_type {
+                    SectionType::Jinja if out_position <= snippet.generated().0 => snippet.in_span.start(),
+                    SectionType::Jinja => snippet.in_span.end(),
+                    SectionType::Sql => snippet.in_span.start() + (out_position - snippet.out_span.0),
+                };
+                return pest::Position::new(self.src, in_position);
+            }
+        }
+        None
+    }
+
+    /// Translates a position in the source file to the corresponding position in the rendered output. Positions inside
+    /// a Jinja expression have none, the end of one maps to the end of its output.
+    pub fn translate_to_output(&self, in_position: usize) -> Option<usize> {
+        let snippets = self.snippets.as_ref()?;
+        snippets.iter().find_map(|snippet| match snippet.section_type {
+            SectionType::Sql if in_position >= snippet.in_span.start() && in_position <= snippet.in_span.end() => {
+                Some(snippet.out_span.0 + (in_position - snippet.in_span.start()))
+            }
+            SectionType::Jinja if in_position == snippet.in_span.end() => Some(snippet.out_span.1),
+            _ => None,
+        })
+    }
+
+    /// Whether the span in the rendered output lies entirely inside the output of a single Jinja expression,
+    /// including its padding
+    pub fn is_generated(&self, out_start: usize, out_end: usize) -> bool {
+        self.snippets.iter().flatten().any(|snippet| {
+            snippet.section_type == SectionType::Jinja
+                && out_start >= snippet.out_span.0
+                && out_start < snippet.out_span.1
+                && out_end <= snippet.out_span.1
+        })
+    }
+
+    /// Whether the span in the rendered output lies entirely inside the padding around the output of a Jinja
+    /// expression, which does not exist in the template
+    pub fn is_padding(&self, out_start: usize, out_end: usize) -> bool {
+        self.snippets.iter().flatten().any(|snippet| {
+            let (start, end) = snippet.generated();
+            snippet.section_type == SectionType::Jinja
+                && ((out_start >= snippet.out_span.0 && out_end <= start)
+                    || (out_start >= end && out_end <= snippet.out_span.1))
+        })
+    }
+
+    /// Whether changing the span in the rendered output would change the output of a Jinja expression.
+    /// Inserting at either end of an expression's output, or changing its padding, does not.
+    pub fn touches_generated(&self, out_start: usize, out_end: usize) -> bool {
+        self.snippets.iter().flatten().any(|snippet| {
+            let (start, end) = snippet.generated();
+            snippet.section_type == SectionType::Jinja
+                && if out_start == out_end {
+                    out_start > start && out_start < end
+                } else {
+                    out_start < end && out_end > start
+                }
+        })
+    }
+
+    /// Translates a span in the rendered output to the corresponding span in the source file
+    pub fn translate_span(&self, out_start: usize, out_end: usize) -> Option<pest::Span<'i>> {
+        // An empty span at the very end of the output has no snippet starting at it
+        let start = self
+            .translate(out_start)
+            .or_else(|| self.translate_end(out_start))?;
+        let end = self.translate_end(out_end)?;
+        pest::Span::new(self.src, start.pos(), end.pos().max(start.pos()))
+    }
+}
+
+fn jinja_block(pair: Pair<Rule>) -> Option<JinjaBlockKind> {
+    match pair.as_rule() {
+        Rule::macro_template => {
+            let mut inner = pair.into_inner();
+            let name = inner.next()?.as_str().to_string();
+            let args = match inner.next() {
+                Some(args) if args.as_rule() == Rule::macro_args => {
+                    args.into_inner()
+                        .flat_map(|arg| arg.into_inner().next())
+                        .map(|identifier| identifier.as_str().to_string())
+                        .collect()
+                }
+                _ => vec![],
+            };
+            Some(JinjaBlockKind::Macro { name, args })
+        }
+        Rule::expr_template => pair
+            .into_inner()
+            .flatten()
+            .any(|pair| pair.as_rule() == Rule::config)
+            .then_some(JinjaBlockKind::Config),
+        _ => None,
+    }
 }
 
 pub fn parse_pair(pair: Pair<Rule>) -> Option<String> {
     match pair.as_rule() {
         Rule::expr_template => {
             let mut out = String::new();
-            out.push_str(" ");
+            out.push(' ');
             for pair in pair.into_inner() {
                 if let Some(s) = parse_pair(pair) {
                     out.push_str(&s);
                 }
             }
-            out.push_str(" ");
-
+            out.push(' ');
             Some(out)
         }
         Rule::expression => {
@@ -174,8 +316,7 @@ pub fn parse_pair(pair: Pair<Rule>) -> Option<String> {
         Rule::reference => {
             let inner_str = pair
                 .into_inner()
-                .filter(|pair| pair.as_rule() == Rule::string)
-                .next()
+                .find(|pair| pair.as_rule() == Rule::string)
                 .unwrap()
                 .as_str();
             Some(inner_str[1..inner_str.len() - 1].to_string())
@@ -198,6 +339,7 @@ pub fn parse_pair(pair: Pair<Rule>) -> Option<String> {
         Rule::not_jinja => {
             Some(pair.as_str().to_string())
         }
+        Rule::macro_template | Rule::comment_template => Some(String::new()),
         _ => None,
     }
 }
@@ -208,7 +350,7 @@ pub fn contains_unknown_jinja(pairs: Pairs<Rule>) -> bool {
             return true;
         }
     }
-    return false;
+    false
 }
 
 #[test]
@@ -220,3 +362,25 @@ fn test_parse() {
 
     println!("Output: {}", translator.output());
 }
+
+#[test]
+fn test_padding() {
+    // Jinja output is padded, so it stays apart from the SQL and the other output next to it
+    let src = "select a from{{ ref('orders') }}where a > 1";
+    let mut translator = JinjaParser::new(src);
+    translator.render_jinja().unwrap();
+    assert_eq!(translator.output(), "select a from orders where a > 1");
+    let padding = (13, 14);
+    assert!(translator.is_padding(padding.0, padding.1));
+    assert!(!translator.touches_generated(padding.0, padding.1));
+    assert!(translator.touches_generated(14, 20));
+    // The padding maps to the edges of the expression
+    assert_eq!(translator.translate_span(padding.0, padding.1).unwrap().as_str(), "");
+    assert_eq!(translator.translate_span(9, 14).unwrap().as_str(), "from");
+    assert_eq!(translator.translate_span(20, 26).unwrap().as_str(), "where");
+
+    let src = "select * from {{ ref('a') }}{{ source('raw', 'b') }}";
+    let mut translator = JinjaParser::new(src);
+    translator.render_jinja().unwrap();
+    assert_eq!(translator.output(), "select * from  a  raw_b ");
+}
@@ -1,195 +1,114 @@
 use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
 
-use serde::Deserialize;
 use tower_lsp::lsp_types::{
-    Diagnostic, DidChangeTextDocumentParams, DidOpenTextDocumentParams, InitializeParams,
-    InitializeResult, MessageType, Position, ServerCapabilities, TextDocumentItem, TextDocumentSyncKind,
+    CodeActionKind, CodeActionOptions, CodeActionParams, CodeActionProviderCapability,
+    CodeActionResponse, CompletionOptions, CompletionParams, CompletionResponse, Diagnostic, DiagnosticSeverity, DidChangeTextDocumentParams, DidCloseTextDocumentParams, DidOpenTextDocumentParams,
+    DidSaveTextDocumentParams, DocumentFormattingParams, DocumentRangeFormattingParams,
+    DocumentSymbolParams, DocumentSymbolResponse, Hover, HoverParams, HoverProviderCapability, InitializeParams, InitializeResult,
+    InitializedParams, MessageType, OneOf, Position, ServerCapabilities, SignatureHelp, SignatureHelpOptions,
+    SignatureHelpParams, SymbolInformation, TextDocumentPositionParams,
+    TextDocumentItem, TextDocumentSyncCapability, TextDocumentSyncKind, TextDocumentSyncOptions,
+    TextDocumentSyncSaveOptions, TextEdit, Range, Url, WorkspaceSymbolParams,
 };
 use tower_lsp::{Client, LanguageServer, LspService, Server, jsonrpc};
-use async_process::{Command};
-use async_std::io::{self, prelude::*};
 
+use crate::code_actions;
+use crate::completion;
+use crate::diagnostics::DiagnosticsScheduler;
+use crate::dialect::Dialect;
+use crate::document_symbols;
+use crate::documents::{self, DocumentStore};
+use crate::formatting;
+use crate::hover;
 use crate::jinja_parser::JinjaParser;
-use crate::parser::{self, Model};
+use crate::parser;
+use crate::settings::Settings;
+use crate::signature_help;
+use crate::linters::{LintError, Linters};
+use crate::sqlfluff::Sqlfluff;
+use crate::utils::Span;
+use crate::workspace::WorkspaceIndex;
 
 struct Backend {
     client: Client,
-    models : HashMap<String, Model> 
+    root : RwLock<Option<PathBuf>>,
+    index : Arc<RwLock<WorkspaceIndex>>,
+    documents : RwLock<DocumentStore>,
+    settings : RwLock<Settings>,
+    /// The dialect models are parsed and linted as
+    dialect : RwLock<Dialect>,
+    sqlfluff : RwLock<Arc<Sqlfluff>>,
+    linters : RwLock<Arc<Linters>>,
+    diagnostics : DiagnosticsScheduler,
+    /// The last error linting each open document failed with. Documents whose last lint worked have none.
+    last_lint_error : Arc<Mutex<HashMap<Url, String>>>
 }
 
-#[derive(Debug)]
-enum LintError {
-    Io(io::Error),
-    Json(serde_json::Error),
-    CannotOpenStdin,
-    CannotOpenStdout
-}
-
-impl From<io::Error> for LintError {
-    fn from(err: io::Error) -> Self {
-        LintError::Io(err)
-    }
-}
-
-impl From<serde_json::Error> for LintError {
-    fn from(err: serde_json::Error) -> Self {
-        LintError::Json(err)
-    }
-}
-
-#[derive(Deserialize, Debug)]
-struct SqlfluffLint {
-    line_no : usize,
-    line_pos : usize,
-    code : String,
-    description : String,
-    name : String
-}
-
-#[derive(Deserialize)]
-struct SqlfluffLints {
-    filepath : String,
-    #[serde(rename = "violations")]
-    lints: Vec<SqlfluffLint>
-}
-
-async fn lint(text : &str) -> Result<SqlfluffLints, LintError> {
-    // Spawn the process
-    let mut child = Command::new("sqlfluff")
-        .arg("lint")
-        .arg("-")
-        .arg("--dialect")
-        .arg("snowflake")
-        .arg("--format")
-        .arg("json")
-        .stdin(async_process::Stdio::piped())
-        .stdout(async_process::Stdio::piped())
-        .spawn()?;
-
-    // Write to the child's stdin
-    
-    if let Some(mut stdin) = child.stdin.take() {
-        stdin.write_all(text.as_bytes()).await?;
-        stdin.flush().await?;    
-    } else {
-        return Err(LintError::CannotOpenStdin)
-    };
-    
-
-    // Read from the child's stdout
-    let output = if let Some(mut stdout) = child.stdout.take() {
-        let mut output = String::new();
-        stdout.read_to_string(&mut output).await?;
-//        println!("Output: {}", &output[1..output.len()-3]);
-        output
-    } else {
-        return Err(LintError::CannotOpenStdout)
-    };
-    
-    let lints : SqlfluffLints = serde_json::from_str(&output[1..output.len()-3])?;
-
-//    for lint in lints.lints.iter() {
-//        println!("Lint: {:?}", lint);
-//    }
-//    println!("{}", lints.lints.len());
-
-    // Wait for the child process to exit
-    let status = child.status().await?;
-
-    Ok(lints)
-}
-
-#[derive(Debug)]
-enum LintError {
-    Io(io::Error),
-    Json(serde_json::Error),
-    CannotOpenStdin,
-    CannotOpenStdout
-}
-
-impl From<io::Error> for LintError {
-    fn from(err: io::Error) -> Self {
-        LintError::Io(err)
-    }
-}
-
-impl From<serde_json::Error> for LintError {
-    fn from(err: serde_json::Error) -> Self {
-        LintError::Json(err)
+/// Records the error linting the document at `uri` failed with, or that it worked. Whether the error is new for the
+/// document, and so should be shown.
+fn record_lint_error(last_lint_error: &mut HashMap<Url, String>, uri: &Url, message: Option<&str>) -> bool {
+    match message {
+        Some(message) => last_lint_error.insert(uri.clone(), message.to_string()).as_deref() != Some(message),
+        None => {
+            last_lint_error.remove(uri);
+            false
+        }
     }
 }
 
-#[derive(Deserialize, Debug)]
-struct SqlfluffLint {
-    line_no : usize,
-    line_pos : usize,
-    code : String,
-    description : String,
-    name : String
-}
-
-#[derive(Deserialize)]
-struct SqlfluffLints {
-    filepath : String,
-    #[serde(rename = "violations")]
-    lints: Vec<SqlfluffLint>
-}
-
-async fn lint(text : &str) -> Result<SqlfluffLints, LintError> {
-    // Spawn the process
-    let mut child = Command::new("sqlfluff")
-        .arg("lint")
-        .arg("-")
-        .arg("--dialect")
-        .arg("snowflake")
-        .arg("--format")
-        .arg("json")
-        .stdin(async_process::Stdio::piped())
-        .stdout(async_process::Stdio::piped())
-        .spawn()?;
-
-    // Write to the child's stdin
-    
-    if let Some(mut stdin) = child.stdin.take() {
-        stdin.write_all(text.as_bytes()).await?;
-        stdin.flush().await?;    
-    } else {
-        return Err(LintError::CannotOpenStdin)
-    };
-    
-
-    // Read from the child's stdout
-    let output = if let Some(mut stdout) = child.stdout.take() {
-        let mut output = String::new();
-        stdout.read_to_string(&mut output).await?;
-//        println!("Output: {}", &output[1..output.len()-3]);
-        output
-    } else {
-        return Err(LintError::CannotOpenStdout)
-    };
-    
-    let lints : SqlfluffLints = serde_json::from_str(&output[1..output.len()-3])?;
-
-//    for lint in lints.lints.iter() {
-//        println!("Lint: {:?}", lint);
-//    }
-//    println!("{}", lints.lints.len());
-
-    // Wait for the child process to exit
-    let status = child.status().await?;
-
-    Ok(lints)
-}
-
 impl Backend {
     async fn initialize(&self, params: InitializeParams) -> jsonrpc::Result<InitializeResult> {
         self.client
             .log_message(MessageType::INFO, "Initialized!")
             .await;
+        #[allow(deprecated)]
+        let root = params
+            .workspace_folders
+            .and_then(|folders| folders.into_iter().next())
+            .map(|folder| folder.uri)
+            .or(params.root_uri)
+            .and_then(|uri| uri.to_file_path().ok());
+        let settings = Settings::from_initialization_options(params.initialization_options);
+        let dialect = Dialect::resolve(settings.dialect, root.as_deref());
+        self.client
+            .log_message(MessageType::INFO, format!("Using the {} dialect", dialect.display_name()))
+            .await;
+        let sqlfluff = Arc::new(Sqlfluff::new(&settings, root.as_deref(), dialect));
+        *self.linters.write().unwrap() = Arc::new(Linters::new(&settings, &sqlfluff, root.as_deref()));
+        *self.sqlfluff.write().unwrap() = sqlfluff;
+        *self.root.write().unwrap() = root;
+        *self.dialect.write().unwrap() = dialect;
+        *self.settings.write().unwrap() = settings;
+
         let capabilities = ServerCapabilities {
-            text_document_sync: Some(tower_lsp::lsp_types::TextDocumentSyncCapability::Kind(
-                TextDocumentSyncKind::FULL
+            text_document_sync: Some(TextDocumentSyncCapability::Options(
+                TextDocumentSyncOptions {
+                    open_close: Some(true),
+                    change: Some(TextDocumentSyncKind::INCREMENTAL),
+                    save: Some(TextDocumentSyncSaveOptions::Supported(true)),
+                    ..Default::default()
+                }
             )),
+            completion_provider: Some(CompletionOptions::default()),
+            hover_provider: Some(HoverProviderCapability::Simple(true)),
+            signature_help_provider: Some(SignatureHelpOptions {
+                trigger_characters: Some(vec!["(".into(), ",".into()]),
+                retrigger_characters: None,
+                work_done_progress_options: Default::default(),
+            }),
+            document_symbol_provider: Some(OneOf::Left(true)),
+            workspace_symbol_provider: Some(OneOf::Left(true)),
+            document_formatting_provider: Some(OneOf::Left(true)),
+            document_range_formatting_provider: Some(OneOf::Left(true)),
+            code_action_provider: Some(CodeActionProviderCapability::Options(CodeActionOptions {
+                code_action_kinds: Some(vec![
+                    CodeActionKind::QUICKFIX,
+                    CodeActionKind::SOURCE_FIX_ALL,
+                ]),
+                ..Default::default()
+            })),
             ..ServerCapabilities::default()
         };
         let result = InitializeResult {
@@ -203,7 +122,32 @@ impl Backend {
         Ok(())
     }
 
-    async fn find_diagnostics(src: &str) -> Vec<Diagnostic> {
+    /// Rebuilds the workspace index from the files on disk
+    async fn index_workspace(&self) {
+        let Some(root) = self.root.read().unwrap().clone() else {
+            return;
+        };
+        let index = self.index.clone();
+        let dialect = *self.dialect.read().unwrap();
+        let indexing = tokio::task::spawn_blocking(move || {
+            *index.write().unwrap() = WorkspaceIndex::index(&root, dialect);
+        });
+        if let Err(e) = indexing.await {
+            self.client
+                .log_message(MessageType::ERROR, format!("Indexing the workspace failed: {}", e))
+                .await;
+        }
+    }
+
+    /// The diagnostics of the document, and the errors of the linters that could not lint it. Calls of unknown
+    /// functions are only reported if `unknown_functions` is set.
+    async fn find_diagnostics(
+        src: &str,
+        path: Option<&Path>,
+        dialect: Dialect,
+        unknown_functions: bool,
+        linters: &Linters,
+    ) -> (Vec<Diagnostic>, Vec<(String, LintError)>) {
         let mut jinja_parse = JinjaParser::new(src);
         match jinja_parse.render_jinja() {
             Ok(_) => {}
@@ -221,93 +165,210 @@ impl Backend {
                     },
                     e,
                 );
-                return vec![diagnostic];
+                return (vec![diagnostic], vec![]);
             }
         }
 
-        match parser::parse_sql(&jinja_parse) {
-            Ok(_) => {
-                match lint(jinja_parse.output()).await {
-                    Ok(lints) => {
-                        let mut diagnostics = vec![];
-                        for lint in lints.lints.iter() {
-                            
-
-                            let diagnostic = Diagnostic::new_simple(
-                                tower_lsp::lsp_types::Range {
-                                    start: Position {
-                                        line: lint.line_no as u32 - 1,
-                                        character: lint.line_pos as u32 - 1,
-                                    },
-                                    end: Position {
-                                        line: lint.line_no as u32 - 1,
-                                        character: lint.line_pos as u32 - 1,
-                                    },
-                                },
-                                lint.description.clone(),
-                            );
-                            diagnostics.push(diagnostic);
-                        }
-                        return diagnostics;
-                    }
-                    Err(_) => {
-                        return vec![]
-                    } 
-                }
-            },
-            Err(e) => {
-                let range = match e.position() {
-                    parser::ErrorLoc::Position(pos) => {
-                        let line = pos.line_col().0 as u32-1;
-                        let column = pos.line_col().1 as u32;
-                        let start = Position {
-                            line,
-                            character: column,
-                        };
-                        let end = Position {
-                            line,
-                            character: column,
-                        };
-                        tower_lsp::lsp_types::Range { start, end }
-                    }
-                    parser::ErrorLoc::Span(span) => {
-                        let start = Position {
-                            line: span.start_pos().line_col().0 as u32-1,
-                            character: span.start_pos().line_col().1 as u32,
-                        };
-                        let end = Position {
-                            line: span.end_pos().line_col().0 as u32-1,
-                            character: span.end_pos().line_col().1 as u32,
-                        };
-                        tower_lsp::lsp_types::Range { start, end }
-                    }
-                    parser::ErrorLoc::Unknown => tower_lsp::lsp_types::Range {
-                        start: Position {
-                            line: 0,
-                            character: 0,
-                        },
-                        end: Position {
-                            line: 0,
-                            character: 0,
-                        },
-                    },
-                };
-                return vec![Diagnostic::new_simple(range, e.message().into())];
-            }
+        // Macro files and other files without SQL render to nothing
+        if jinja_parse.output().trim().is_empty() {
+            return (vec![], vec![]);
+        }
+
+        let result = parser::parse_sql(&jinja_parse, "", dialect);
+        let diagnostic = |e: &parser::SqlParseError, severity| {
+            let range = match e.position() {
+                parser::ErrorLoc::Span(span) => Span::from_span(*span).to_range(),
+                parser::ErrorLoc::Unknown => Range::default(),
+            };
+            // The server's own errors are told apart from sqlfluff's by their source
+            Diagnostic::new(range, Some(severity), None, Some("dbt-lsp".into()), e.message().into(), None, None)
+        };
+        // The checks of function calls are warnings, and do not keep the linters from running
+        let mut warnings: Vec<Diagnostic> = result
+            .warnings
+            .iter()
+            .map(|e| diagnostic(e, DiagnosticSeverity::WARNING))
+            .collect();
+        if unknown_functions {
+            warnings.extend(
+                result
+                    .unknown_functions
+                    .iter()
+                    .map(|e| diagnostic(e, DiagnosticSeverity::INFORMATION)),
+            );
         }
+        if result.errors.is_empty() {
+            let (mut diagnostics, lint_errors) = linters.lint(&jinja_parse, path).await;
+            diagnostics.extend(warnings);
+            return (diagnostics, lint_errors);
+        }
+        let mut diagnostics: Vec<Diagnostic> = result
+            .errors
+            .iter()
+            .map(|e| diagnostic(e, DiagnosticSeverity::ERROR))
+            .collect();
+        diagnostics.extend(warnings);
+        (diagnostics, vec![])
     }
 
-    // TODO: This function should notice parsing errors. Should be called by onDidChange and onDidOpen functions.
     async fn on_change(&self, params: TextDocumentItem) {
 
         self.client.log_message(MessageType::INFO, "OnChange Called!").await;
-        let parsing_base = params.text.clone();
-        
-        let diagnostics = Backend::find_diagnostics(&parsing_base).await;
-        self.client
-            .publish_diagnostics(params.uri, diagnostics, Some(params.version))
+        let parsing_base = params.text;
+        let debounce = self.settings.read().unwrap().diagnostics_debounce();
+        let dialect = *self.dialect.read().unwrap();
+        let unknown_functions = self.settings.read().unwrap().unknown_functions;
+        let linters = self.linters.read().unwrap().clone();
+        let client = self.client.clone();
+        let uri = params.uri.clone();
+        let path = params.uri.to_file_path().ok();
+        let version = params.version;
+        let last_lint_error = self.last_lint_error.clone();
+
+        self.diagnostics
+            .schedule(
+                params.uri,
+                params.version,
+                debounce,
+                async move {
+                    Backend::find_diagnostics(&parsing_base, path.as_deref(), dialect, unknown_functions, &linters).await
+                },
+                move |(diagnostics, lint_errors)| async move {
+                    // The same errors come back with every edit, so they are only shown when they change
+                    let message = (!lint_errors.is_empty()).then(|| {
+                        lint_errors
+                            .iter()
+                            .map(|(linter, e)| format!("{} failed: {}", linter, e))
+                            .collect::<Vec<_>>()
+                            .join("; ")
+                    });
+                    let changed = record_lint_error(&mut last_lint_error.lock().unwrap(), &uri, message.as_deref());
+                    if let (true, Some(message)) = (changed, message) {
+                        client
+                            .show_message(MessageType::ERROR, format!("Could not lint {}: {}", uri, message))
+                            .await;
+                    }
+                    client.publish_diagnostics(uri, diagnostics, Some(version)).await
+                },
+            )
             .await;
     }
+
+    async fn document_symbol(
+        &self,
+        params: DocumentSymbolParams,
+    ) -> jsonrpc::Result<Option<DocumentSymbolResponse>> {
+        let uri = params.text_document.uri;
+        let Some(src) = self
+            .documents
+            .read()
+            .unwrap()
+            .get(&uri)
+            .map(|document| document.rope.to_string())
+        else {
+            return Ok(None);
+        };
+        let name = model_name(&uri);
+        let dialect = *self.dialect.read().unwrap();
+        Ok(Some(DocumentSymbolResponse::Nested(
+            document_symbols::document_symbols(&src, &name, dialect),
+        )))
+    }
+
+    /// Completes the built-in functions of the dialect. The client filters them by what has been typed.
+    async fn completion(&self, _params: CompletionParams) -> jsonrpc::Result<Option<CompletionResponse>> {
+        let dialect = *self.dialect.read().unwrap();
+        Ok(Some(CompletionResponse::Array(completion::function_completions(dialect))))
+    }
+
+    /// The source of an open document, and the byte offset of a position in it
+    fn source_offset(&self, position: &TextDocumentPositionParams) -> Option<(String, usize)> {
+        self.documents
+            .read()
+            .unwrap()
+            .get(&position.text_document.uri)
+            .map(|document| {
+                let index = documents::position_to_char(&document.rope, position.position);
+                (document.rope.to_string(), document.rope.char_to_byte(index))
+            })
+    }
+
+    /// The signatures of the function call the cursor is in
+    async fn signature_help(&self, params: SignatureHelpParams) -> jsonrpc::Result<Option<SignatureHelp>> {
+        let Some((src, offset)) = self.source_offset(&params.text_document_position_params) else {
+            return Ok(None);
+        };
+        let dialect = *self.dialect.read().unwrap();
+        Ok(signature_help::signature_help(&src, offset, dialect))
+    }
+
+    /// The documentation of the function or clause keyword under the cursor
+    async fn hover(&self, params: HoverParams) -> jsonrpc::Result<Option<Hover>> {
+        let Some((src, offset)) = self.source_offset(&params.text_document_position_params) else {
+            return Ok(None);
+        };
+        let dialect = *self.dialect.read().unwrap();
+        Ok(hover::hover(&src, offset, dialect))
+    }
+
+    async fn symbol(
+        &self,
+        params: WorkspaceSymbolParams,
+    ) -> jsonrpc::Result<Option<Vec<SymbolInformation>>> {
+        Ok(Some(self.index.read().unwrap().symbols(&params.query)))
+    }
+
+    /// Formats the whole document, or only `range` of it
+    async fn format(&self, uri: Url, range: Option<Range>) -> jsonrpc::Result<Option<Vec<TextEdit>>> {
+        let Some(src) = self
+            .documents
+            .read()
+            .unwrap()
+            .get(&uri)
+            .map(|document| document.rope.to_string())
+        else {
+            return Ok(None);
+        };
+        let rules = self.settings.read().unwrap().formatting_rules.clone();
+        let sqlfluff = self.sqlfluff.read().unwrap().clone();
+        let path = uri.to_file_path().ok();
+        Ok(formatting::format(&src, path.as_deref(), range, &rules, &sqlfluff).await)
+    }
+
+    async fn code_action(&self, params: CodeActionParams) -> jsonrpc::Result<Option<CodeActionResponse>> {
+        let uri = params.text_document.uri;
+        let Some((src, version)) = self
+            .documents
+            .read()
+            .unwrap()
+            .get(&uri)
+            .map(|document| (document.rope.to_string(), document.version))
+        else {
+            return Ok(None);
+        };
+        let sqlfluff = self.sqlfluff.read().unwrap().clone();
+        let path = uri.to_file_path().ok();
+        let actions = code_actions::code_actions(
+            &uri,
+            version,
+            &src,
+            path.as_deref(),
+            &params.context.diagnostics,
+            params.context.only.as_deref(),
+            &sqlfluff,
+        )
+        .await;
+        Ok(Some(actions))
+    }
+}
+
+/// The model name of a file is its file name without the extension
+fn model_name(uri: &Url) -> String {
+    uri.path_segments()
+        .and_then(|mut segments| segments.next_back())
+        .and_then(|file_name| file_name.split('.').next())
+        .unwrap_or_default()
+        .to_string()
 }
 
 struct BackendExecutor {
@@ -320,22 +381,40 @@ impl LanguageServer for BackendExecutor {
         self.backend.initialize(params).await
     }
 
+    async fn initialized(&self, _params: InitializedParams) {
+        self.backend.index_workspace().await
+    }
+
     async fn shutdown(&self) -> jsonrpc::Result<()> {
         self.backend.shutdown().await
     }
 
-    async fn did_change(&self, mut params: DidChangeTextDocumentParams) {
+    async fn did_change(&self, params: DidChangeTextDocumentParams) {
+        let uri = params.text_document.uri;
+        let version = params.text_document.version;
+        let text = {
+            let mut documents = self.backend.documents.write().unwrap();
+            match documents.apply_changes(&uri, version, params.content_changes) {
+                Some(document) => document.rope.to_string(),
+                None => return,
+            }
+        };
         self.backend
             .on_change(TextDocumentItem {
-                uri: params.text_document.uri,
+                uri,
                 language_id: "sql".into(),
-                version: params.text_document.version,
-                text: std::mem::take(&mut params.content_changes[0].text),
+                version,
+                text,
             })
             .await;
     }
 
     async fn did_open(&self, params: DidOpenTextDocumentParams) {
+        self.backend.documents.write().unwrap().open(
+            params.text_document.uri.clone(),
+            &params.text_document.text,
+            params.text_document.version,
+        );
         self.backend
             .on_change(TextDocumentItem {
                 uri: params.text_document.uri,
@@ -345,11 +424,79 @@ impl LanguageServer for BackendExecutor {
             })
             .await;
     }
+
+    async fn document_symbol(
+        &self,
+        params: DocumentSymbolParams,
+    ) -> jsonrpc::Result<Option<DocumentSymbolResponse>> {
+        self.backend.document_symbol(params).await
+    }
+
+    async fn completion(&self, params: CompletionParams) -> jsonrpc::Result<Option<CompletionResponse>> {
+        self.backend.completion(params).await
+    }
+
+    async fn signature_help(&self, params: SignatureHelpParams) -> jsonrpc::Result<Option<SignatureHelp>> {
+        self.backend.signature_help(params).await
+    }
+
+    async fn hover(&self, params: HoverParams) -> jsonrpc::Result<Option<Hover>> {
+        self.backend.hover(params).await
+    }
+
+    async fn did_close(&self, params: DidCloseTextDocumentParams) {
+        self.backend.diagnostics.cancel(&params.text_document.uri).await;
+        self.backend.last_lint_error.lock().unwrap().remove(&params.text_document.uri);
+        self.backend
+            .documents
+            .write()
+            .unwrap()
+            .close(&params.text_document.uri);
+    }
+
+    async fn did_save(&self, _params: DidSaveTextDocumentParams) {
+        self.backend.index_workspace().await
+    }
+
+    async fn symbol(
+        &self,
+        params: WorkspaceSymbolParams,
+    ) -> jsonrpc::Result<Option<Vec<SymbolInformation>>> {
+        self.backend.symbol(params).await
+    }
+
+    async fn code_action(&self, params: CodeActionParams) -> jsonrpc::Result<Option<CodeActionResponse>> {
+        self.backend.code_action(params).await
+    }
+
+    async fn formatting(&self, params: DocumentFormattingParams) -> jsonrpc::Result<Option<Vec<TextEdit>>> {
+        self.backend.format(params.text_document.uri, None).await
+    }
+
+    async fn range_formatting(
+        &self,
+        params: DocumentRangeFormattingParams,
+    ) -> jsonrpc::Result<Option<Vec<TextEdit>>> {
+        self.backend
+            .format(params.text_document.uri, Some(params.range))
+            .await
+    }
 }
 
 pub async fn run() {
     let (service, socket) = LspService::new(|client| BackendExecutor {
-        backend: Backend { client, models : HashMap::new() },
+        backend: Backend {
+            client,
+            root : RwLock::new(None),
+            index : Arc::new(RwLock::new(WorkspaceIndex::default())),
+            documents : RwLock::new(DocumentStore::default()),
+            settings : RwLock::new(Settings::default()),
+            dialect : RwLock::new(Dialect::default()),
+            sqlfluff : RwLock::new(Arc::new(Sqlfluff::default())),
+            linters : RwLock::new(Arc::new(Linters::default())),
+            diagnostics : DiagnosticsScheduler::default(),
+            last_lint_error : Arc::new(Mutex::new(HashMap::new())),
+        },
     });
 
     let stdin = tokio::io::stdin();
@@ -389,10 +536,65 @@ mod tests {
         
         select * from renamed
         "#;
-        let lints = lint(src).await;
+        let lints = crate::sqlfluff::lint("sqlfluff", &[], None, src).await;
         lints.unwrap();
 
-        let diagnostics = Backend::find_diagnostics(src).await;
+        let (diagnostics, lint_errors) = Backend::find_diagnostics(src, None, Dialect::Snowflake, false, &Linters::default()).await;
+        assert!(lint_errors.is_empty());
         assert_eq!(diagnostics.len(), 0);
     }
-}
\ No newline at end of file
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_function_warnings() {
+        use crate::settings::{LinterSettings, OutputFormat};
+
+        let settings = Settings {
+            linters: vec![LinterSettings::Command {
+                name: "echo".into(),
+                command: vec!["sh".into(), "-c".into(), r#"echo '[{"line": 1, "column": 1, "message": "linted"}]'"#.into()],
+                format: OutputFormat::Json,
+            }],
+            ..Settings::default()
+        };
+        let linters = Linters::new(&settings, &Arc::new(Sqlfluff::default()), None);
+        let src = "select customer_id, sum(amount), rpad(name, 10, '.') from {{ ref('orders') }}";
+        let messages = |diagnostics: Vec<Diagnostic>| -> Vec<(Option<DiagnosticSeverity>, String)> {
+            diagnostics.into_iter().map(|d| (d.severity, d.message)).collect()
+        };
+
+        // Warnings do not keep the linters from running
+        let (diagnostics, _) = Backend::find_diagnostics(src, None, Dialect::Snowflake, false, &linters).await;
+        assert_eq!(
+            messages(diagnostics),
+            [
+                (Some(DiagnosticSeverity::WARNING), "linted".to_string()),
+                (
+                    Some(DiagnosticSeverity::WARNING),
+                    "customer_id is neither aggregated nor in a GROUP BY, but the select uses the aggregate function SUM"
+                        .to_string()
+                ),
+            ]
+        );
+        // RPAD is missing from the catalog, so it only looks like a typo of LPAD when asked for
+        let (diagnostics, _) = Backend::find_diagnostics(src, None, Dialect::Snowflake, true, &linters).await;
+        assert_eq!(
+            messages(diagnostics).last(),
+            Some(&(Some(DiagnosticSeverity::INFORMATION), "unknown function rpad, did you mean LPAD?".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_record_lint_error() {
+        let mut last_lint_error = HashMap::new();
+        let customers = Url::parse("file:///project/models/customers.sql").unwrap();
+        let orders = Url::parse("file:///project/models/orders.sql").unwrap();
+        assert!(record_lint_error(&mut last_lint_error, &customers, Some("sqlfluff failed")));
+        assert!(!record_lint_error(&mut last_lint_error, &customers, Some("sqlfluff failed")));
+        // The same error in another document is shown again, and a clean lint only clears its own document
+        assert!(record_lint_error(&mut last_lint_error, &orders, Some("sqlfluff failed")));
+        assert!(!record_lint_error(&mut last_lint_error, &orders, None));
+        assert!(!record_lint_error(&mut last_lint_error, &customers, Some("sqlfluff failed")));
+        assert!(record_lint_error(&mut last_lint_error, &orders, Some("sqlfluff failed")));
+    }
+}
@@ -0,0 +1,552 @@
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use async_process::Command;
+use async_std::io::{self, prelude::*};
+use serde::Deserialize;
+use tower_lsp::async_trait;
+use tower_lsp::lsp_types::{CodeDescription, Diagnostic, DiagnosticSeverity, NumberOrString, Range, Url};
+
+use crate::jinja_parser::JinjaParser;
+use crate::settings::{LinterSettings, OutputFormat, Settings, Severity};
+use crate::sqlfluff::Sqlfluff;
+use crate::utils::Span;
+
+#[derive(Debug)]
+pub enum LintError {
+    Io(io::Error),
+    /// The executable does not exist
+    NotFound(String),
+    Json(serde_json::Error),
+    CannotOpenStdin,
+    CannotOpenStdout,
+    /// The linter exited with an error rather than with a status that only means it found problems
+    Failed { status: Option<i32>, stderr: String },
+    /// The sqlfluff worker did not answer within the timeout
+    WorkerTimeout,
+    /// The sqlfluff worker exited or answered with something that is not a response
+    WorkerCrashed,
+    /// The sqlfluff worker answered with an error, e.g. because sqlfluff is not installed
+    Worker(String)
+}
+
+impl Display for LintError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LintError::Io(e) => write!(f, "{}", e),
+            LintError::NotFound(executable) => write!(f, "`{}` was not found", executable),
+            LintError::Json(e) => write!(f, "the output is not valid JSON: {}", e),
+            LintError::CannotOpenStdin => write!(f, "could not write to the linter"),
+            LintError::CannotOpenStdout => write!(f, "could not read from the linter"),
+            LintError::Failed { status, stderr } => {
+                match status {
+                    Some(status) => write!(f, "exited with status {}", status)?,
+                    None => write!(f, "was killed")?,
+                }
+                // The last line of a Python traceback is the error itself
+                match stderr.lines().rev().find(|line| !line.trim().is_empty()) {
+                    Some(line) => write!(f, ": {}", line.trim()),
+                    None => Ok(()),
+                }
+            }
+            LintError::WorkerTimeout => write!(f, "the sqlfluff worker did not finish in time"),
+            LintError::WorkerCrashed => write!(f, "the sqlfluff worker crashed"),
+            LintError::Worker(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<io::Error> for LintError {
+    fn from(err: io::Error) -> Self {
+        LintError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for LintError {
+    fn from(err: serde_json::Error) -> Self {
+        LintError::Json(err)
+    }
+}
+
+/// A model rendered from its template, which is what linters see
+pub struct RenderedModel<'a> {
+    pub sql: &'a str,
+    /// The path of the template, which linters use to find their config
+    pub path: Option<&'a Path>,
+}
+
+/// A problem a linter found in the rendered SQL
+#[derive(Debug, PartialEq)]
+pub struct LintResult {
+    /// 1-based line and character in the rendered SQL
+    pub line: usize,
+    pub column: usize,
+    /// The exclusive end, if the linter reports one
+    pub end: Option<(usize, usize)>,
+    pub code: Option<String>,
+    pub message: String,
+    pub severity: Severity,
+    pub documentation: Option<Url>,
+}
+
+impl LintResult {
+    /// The range of the problem in the template. Problems that lie entirely inside SQL generated by a Jinja
+    /// expression can not be fixed where they are reported, so they are dropped. Problems that only overlap
+    /// generated SQL are stretched to cover the whole expression.
+    pub fn source_range(&self, jinja_parse: &JinjaParser) -> Option<Range> {
+        let rendered = jinja_parse.output();
+        let start = rendered_offset(rendered, self.line, self.column)?;
+        let end = match self.end {
+            Some((line, column)) => rendered_offset(rendered, line, column)
+                .filter(|end| *end > start)
+                .unwrap_or_else(|| token_end(rendered, start)),
+            None => token_end(rendered, start),
+        };
+        if jinja_parse.is_generated(start, end) {
+            return None;
+        }
+        let span = jinja_parse.translate_span(start, end)?;
+        Some(Span::from_span(span).to_range())
+    }
+
+    pub fn to_diagnostic(&self, source: &str, jinja_parse: &JinjaParser) -> Option<Diagnostic> {
+        Some(Diagnostic {
+            range: self.source_range(jinja_parse)?,
+            severity: Some(self.severity.into()),
+            code: self.code.clone().map(NumberOrString::String),
+            code_description: self.documentation.clone().map(|href| CodeDescription { href }),
+            source: Some(source.into()),
+            message: self.message.clone(),
+            ..Diagnostic::default()
+        })
+    }
+}
+
+/// The byte offset of a 1-based line and character in `text`, clamped to the end of the line
+fn rendered_offset(text: &str, line_no: usize, line_pos: usize) -> Option<usize> {
+    let mut line_start = 0;
+    for _ in 1..line_no {
+        line_start += text[line_start..].find('\n')? + 1;
+    }
+    let line = &text[line_start..];
+    let line = &line[..line.find('\n').unwrap_or(line.len())];
+    let column = line
+        .char_indices()
+        .nth(line_pos.saturating_sub(1))
+        .map_or(line.len(), |(column, _)| column);
+    Some(line_start + column)
+}
+
+/// The end of the token starting at `start`. Linters often only report where a problem starts, so it is assumed to
+/// cover a word, a quoted string, a run of spaces or a single symbol. Problems at a line break are empty.
+fn token_end(text: &str, start: usize) -> usize {
+    let rest = &text[start..];
+    let mut chars = rest.char_indices();
+    let Some((_, first)) = chars.next() else {
+        return start;
+    };
+    let length = match first {
+        '\n' | '\r' => 0,
+        '\'' | '"' => chars
+            .find(|(_, c)| *c == first)
+            .map_or(rest.len(), |(i, c)| i + c.len_utf8()),
+        c if c.is_alphanumeric() || c == '_' => rest
+            .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .unwrap_or(rest.len()),
+        ' ' | '\t' => rest
+            .find(|c: char| c != ' ' && c != '\t')
+            .unwrap_or(rest.len()),
+        c => c.len_utf8(),
+    };
+    start + length
+}
+
+#[async_trait]
+pub trait Linter: Send + Sync {
+    /// The name diagnostics are published under
+    fn name(&self) -> &str;
+
+    async fn lint(&self, model: &RenderedModel<'_>) -> Result<Vec<LintResult>, LintError>;
+}
+
+/// What a process wrote and how it exited
+pub struct ProcessOutput {
+    pub status: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+/// Runs `program` with `args` in `working_dir` and writes `text` to its stdin
+pub async fn run_process(
+    program: &str,
+    args: &[String],
+    working_dir: Option<&Path>,
+    text: &str,
+) -> Result<ProcessOutput, LintError> {
+    let mut command = Command::new(program);
+    command
+        .args(args)
+        .stdin(async_process::Stdio::piped())
+        .stdout(async_process::Stdio::piped())
+        .stderr(async_process::Stdio::piped())
+        .kill_on_drop(true);
+    if let Some(working_dir) = working_dir {
+        command.current_dir(working_dir);
+    }
+    let mut child = command.spawn().map_err(|e| match e.kind() {
+        io::ErrorKind::NotFound => LintError::NotFound(program.into()),
+        _ => LintError::Io(e),
+    })?;
+
+    let Some(mut stdin) = child.stdin.take() else {
+        return Err(LintError::CannotOpenStdin);
+    };
+    // A linter that reads the file instead of stdin may exit before taking all of it
+    match stdin.write_all(text.as_bytes()).await.and(stdin.flush().await) {
+        Err(e) if e.kind() != io::ErrorKind::BrokenPipe => return Err(e.into()),
+        _ => {}
+    }
+    // Closing stdin tells the process the input is complete
+    drop(stdin);
+
+    // Read stdout and stderr at the same time, so a full stderr pipe can not block the child
+    let (Some(mut stdout), Some(mut stderr)) = (child.stdout.take(), child.stderr.take()) else {
+        return Err(LintError::CannotOpenStdout);
+    };
+    let mut output = ProcessOutput {
+        status: None,
+        stdout: String::new(),
+        stderr: String::new(),
+    };
+    let (read_stdout, read_stderr) = futures::join!(
+        stdout.read_to_string(&mut output.stdout),
+        stderr.read_to_string(&mut output.stderr)
+    );
+    read_stdout?;
+    read_stderr?;
+    output.status = child.status().await?.code();
+    Ok(output)
+}
+
+/// A linter run as an external command that reads the rendered SQL from stdin and prints SARIF or JSON.
+/// `{file}` in its arguments is replaced with the path of the template.
+pub struct CommandLinter {
+    name: String,
+    command: Vec<String>,
+    format: OutputFormat,
+    working_dir: Option<PathBuf>,
+}
+
+/// The JSON a command linter prints: a list of these
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonResult {
+    line: usize,
+    column: usize,
+    end_line: Option<usize>,
+    end_column: Option<usize>,
+    code: Option<String>,
+    message: String,
+    severity: Option<Severity>,
+}
+
+#[derive(Deserialize)]
+struct SarifLog {
+    runs: Vec<SarifRun>,
+}
+
+#[derive(Deserialize)]
+struct SarifRun {
+    #[serde(default)]
+    results: Vec<SarifResult>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SarifResult {
+    rule_id: Option<String>,
+    level: Option<String>,
+    message: SarifMessage,
+    #[serde(default)]
+    locations: Vec<SarifLocation>,
+}
+
+#[derive(Deserialize)]
+struct SarifMessage {
+    text: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SarifLocation {
+    physical_location: Option<SarifPhysicalLocation>,
+}
+
+#[derive(Deserialize)]
+struct SarifPhysicalLocation {
+    region: Option<SarifRegion>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SarifRegion {
+    start_line: Option<usize>,
+    start_column: Option<usize>,
+    end_line: Option<usize>,
+    end_column: Option<usize>,
+}
+
+fn parse_json(output: &str) -> Result<Vec<LintResult>, LintError> {
+    let results: Vec<JsonResult> = serde_json::from_str(output)?;
+    Ok(results
+        .into_iter()
+        .map(|result| LintResult {
+            line: result.line,
+            column: result.column,
+            end: result.end_line.zip(result.end_column),
+            code: result.code,
+            message: result.message,
+            severity: result.severity.unwrap_or(Severity::Warning),
+            documentation: None,
+        })
+        .collect())
+}
+
+fn parse_sarif(output: &str) -> Result<Vec<LintResult>, LintError> {
+    let log: SarifLog = serde_json::from_str(output)?;
+    Ok(log
+        .runs
+        .into_iter()
+        .flat_map(|run| run.results)
+        .map(|result| {
+            let region = result
+                .locations
+                .into_iter()
+                .find_map(|location| location.physical_location?.region);
+            let (line, column, end) = match region {
+                Some(region) => {
+                    let line = region.start_line.unwrap_or(1);
+                    // A region without an end line ends on its start line
+                    let end_line = region.end_line.unwrap_or(line);
+                    (line, region.start_column.unwrap_or(1), region.end_column.map(|column| (end_line, column)))
+                }
+                // A result without a location is about the whole file
+                None => (1, 1, None),
+            };
+            let severity = match result.level.as_deref() {
+                Some("error") => Severity::Error,
+                Some("note") => Severity::Information,
+                Some("none") => Severity::Hint,
+                _ => Severity::Warning,
+            };
+            LintResult {
+                line,
+                column,
+                end,
+                code: result.rule_id,
+                message: result.message.text,
+                severity,
+                documentation: None,
+            }
+        })
+        .collect())
+}
+
+#[async_trait]
+impl Linter for CommandLinter {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn lint(&self, model: &RenderedModel<'_>) -> Result<Vec<LintResult>, LintError> {
+        let Some((program, args)) = self.command.split_first() else {
+            return Err(LintError::NotFound(String::new()));
+        };
+        let file = model.path.map_or("-".into(), |path| path.display().to_string());
+        let args: Vec<String> = args.iter().map(|arg| arg.replace("{file}", &file)).collect();
+        let output = run_process(program, &args, self.working_dir.as_deref(), model.sql).await?;
+        // Linters commonly exit with an error status when they find problems, so the output decides
+        if output.stdout.trim().is_empty() {
+            return match output.status {
+                Some(0) => Ok(vec![]),
+                status => Err(LintError::Failed {
+                    status,
+                    stderr: output.stderr,
+                }),
+            };
+        }
+        match self.format {
+            OutputFormat::Json => parse_json(&output.stdout),
+            OutputFormat::Sarif => parse_sarif(&output.stdout),
+        }
+    }
+}
+
+/// The linters enabled for the workspace
+pub struct Linters {
+    linters: Vec<Arc<dyn Linter>>,
+}
+
+impl Default for Linters {
+    fn default() -> Self {
+        Linters::new(&Settings::default(), &Arc::new(Sqlfluff::default()), None)
+    }
+}
+
+impl Linters {
+    pub fn new(settings: &Settings, sqlfluff: &Arc<Sqlfluff>, working_dir: Option<&Path>) -> Linters {
+        let linters = settings
+            .linters
+            .iter()
+            .map(|linter| -> Arc<dyn Linter> {
+                match linter {
+                    LinterSettings::Sqlfluff => sqlfluff.clone(),
+                    LinterSettings::Command {
+                        name,
+                        command,
+                        format,
+                    } => Arc::new(CommandLinter {
+                        name: name.clone(),
+                        command: command.clone(),
+                        format: *format,
+                        working_dir: working_dir.map(Path::to_path_buf),
+                    }),
+                }
+            })
+            .collect();
+        Linters { linters }
+    }
+
+    /// Runs every linter on the rendered template and maps what they find back to the template.
+    /// Returns the diagnostics and the errors of the linters that failed, by name.
+    pub async fn lint(
+        &self,
+        jinja_parse: &JinjaParser<'_>,
+        path: Option<&Path>,
+    ) -> (Vec<Diagnostic>, Vec<(String, LintError)>) {
+        let model = RenderedModel {
+            sql: jinja_parse.output(),
+            path,
+        };
+        let results = futures::future::join_all(self.linters.iter().map(|linter| linter.lint(&model))).await;
+
+        let mut diagnostics = vec![];
+        let mut errors = vec![];
+        for (linter, result) in self.linters.iter().zip(results) {
+            match result {
+                Ok(results) => diagnostics.extend(
+                    results
+                        .iter()
+                        .filter_map(|result| result.to_diagnostic(linter.name(), jinja_parse)),
+                ),
+                Err(e) => {
+                    // Without its results the file gets a single diagnostic saying why
+                    diagnostics.push(Diagnostic::new(
+                        Range::default(),
+                        Some(DiagnosticSeverity::ERROR),
+                        None,
+                        Some(linter.name().into()),
+                        format!("{} could not lint this file: {}", linter.name(), e),
+                        None,
+                        None,
+                    ));
+                    errors.push((linter.name().to_string(), e));
+                }
+            }
+        }
+        (diagnostics, errors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tower_lsp::lsp_types::Position;
+
+    fn range(start: (u32, u32), end: (u32, u32)) -> Range {
+        Range::new(Position::new(start.0, start.1), Position::new(end.0, end.1))
+    }
+
+    fn result(line: usize, column: usize, end: Option<(usize, usize)>) -> LintResult {
+        LintResult {
+            line,
+            column,
+            end,
+            code: None,
+            message: String::new(),
+            severity: Severity::Warning,
+            documentation: None,
+        }
+    }
+
+    #[test]
+    fn test_source_ranges() {
+        let src = "{{ config(materialized='table') }}\n\nselect id,customer_id\nfrom {{ ref('stg_orders') }}\n";
+        let mut jinja_parse = JinjaParser::new(src);
+        jinja_parse.render_jinja().unwrap();
+        assert_eq!(jinja_parse.output(), "  \n\nselect id,customer_id\nfrom  stg_orders \n");
+
+        // A keyword covers the whole word
+        assert_eq!(result(3, 1, None).source_range(&jinja_parse), Some(range((2, 0), (2, 6))));
+        // A symbol covers a single character
+        assert_eq!(result(3, 10, None).source_range(&jinja_parse), Some(range((2, 9), (2, 10))));
+        // The model name only exists in the rendered SQL
+        assert_eq!(result(4, 7, None).source_range(&jinja_parse), None);
+        // A reported end in generated SQL stretches the range over the whole expression
+        assert_eq!(
+            result(4, 1, Some((4, 17))).source_range(&jinja_parse),
+            Some(range((3, 0), (3, 28)))
+        );
+        // The end of the file is empty
+        assert_eq!(result(5, 1, None).source_range(&jinja_parse), Some(range((4, 0), (4, 0))));
+    }
+
+    #[test]
+    fn test_output_formats() {
+        let sarif = r#"{"version": "2.1.0", "runs": [{"tool": {"driver": {"name": "lint"}}, "results": [
+            {"ruleId": "S1", "level": "error", "message": {"text": "Avoid select *"},
+             "locations": [{"physicalLocation": {"artifactLocation": {"uri": "a.sql"}, "region": {"startLine": 2, "startColumn": 8, "endColumn": 9}}}]},
+            {"ruleId": "S2", "level": "note", "message": {"text": "File is long"}}
+        ]}]}"#;
+        let results = parse_sarif(sarif).unwrap();
+        assert_eq!(results[0].code.as_deref(), Some("S1"));
+        assert_eq!((results[0].line, results[0].column, results[0].end), (2, 8, Some((2, 9))));
+        assert_eq!(results[0].severity, Severity::Error);
+        assert_eq!((results[1].line, results[1].column, results[1].end), (1, 1, None));
+        assert_eq!(results[1].severity, Severity::Information);
+
+        let json = r#"[{"line": 3, "column": 2, "endLine": 3, "endColumn": 5, "message": "Unknown column", "severity": "hint"},
+            {"line": 1, "column": 1, "code": "X1", "message": "Missing header"}]"#;
+        let results = parse_json(json).unwrap();
+        assert_eq!(results[0].end, Some((3, 5)));
+        assert_eq!(results[0].severity, Severity::Hint);
+        assert_eq!(results[1].code.as_deref(), Some("X1"));
+        assert_eq!(results[1].severity, Severity::Warning);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_command_linter() {
+        let script = r#"read sql; echo "[{\"line\": 1, \"column\": 8, \"message\": \"$1: $sql\"}]"; exit 1"#;
+        let linter = CommandLinter {
+            name: "echo".into(),
+            command: vec!["sh".into(), "-c".into(), script.into(), "sh".into(), "{file}".into()],
+            format: OutputFormat::Json,
+            working_dir: None,
+        };
+        let model = RenderedModel {
+            sql: "select a from b\n",
+            path: Some(Path::new("models/b.sql")),
+        };
+        let results = linter.lint(&model).await.unwrap();
+        assert_eq!(results[0].message, "models/b.sql: select a from b");
+
+        let failing = CommandLinter {
+            command: vec!["sh".into(), "-c".into(), "echo broken >&2; exit 3".into()],
+            ..linter
+        };
+        let error = failing.lint(&model).await.unwrap_err();
+        assert_eq!(error.to_string(), "exited with status 3: broken");
+    }
+}
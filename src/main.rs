@@ -1,10 +1,34 @@
+mod code_actions;
+mod completion;
+mod dialect;
+mod diagnostics;
+mod document_symbols;
+mod documents;
+mod formatting;
+mod functions;
+mod hover;
 mod jinja_parser;
 mod language_server;
+mod linters;
 mod parser;
+mod recovery;
+mod settings;
+mod signature_help;
+mod sqlfluff;
 mod utils;
 mod webscraping;
+mod workspace;
 
 #[tokio::main]
 async fn main() {
-    language_server::run().await;
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("catalog") => {
+            if let Err(e) = webscraping::catalog_command(&args[1..]).await {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+        _ => language_server::run().await,
+    }
 }
@@ -1,19 +1,20 @@
-use std::{
-    fmt::{Display, Formatter},
-    fs,
-};
+use std::fmt::{Display, Formatter};
+use std::ops::ControlFlow;
 
 use crate::{
+    dialect::Dialect,
+    functions::{self, FunctionKind},
     jinja_parser::JinjaParser,
+    recovery::{self, Body, SyntaxError},
     utils::{FileLocation, Span},
 };
-use pest::{iterators::Pair, Parser, Position};
-use pest_derive::Parser;
-use walkdir::WalkDir;
-
-#[derive(Parser)]
-#[grammar = "snowflake_sql.pest"]
-struct SqlParser;
+use sqlparser::{
+    ast::{
+        CastKind, Expr, Function as FunctionCall, FunctionArguments, GroupByExpr, Ident, ObjectName, Query, Select,
+        SelectItem, SetExpr, SetQuantifier, Spanned, TableAlias, TableFactor, TableWithJoins, Visit, Visitor,
+    },
+    tokenizer::{Location, Span as SqlSpan},
+};
 
 impl Display for Span {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
@@ -22,7 +23,7 @@ impl Display for Span {
 }
 
 impl Span {
-    fn from_span(span: pest::Span) -> Span {
+    pub fn from_span(span: pest::Span) -> Span {
         let start = span.start_pos().line_col();
         let start = FileLocation {
             line: start.0,
@@ -37,178 +38,609 @@ impl Span {
     }
 }
 
-struct Column {
-    name: String,
-    span: Span,
+/// Maps the line and column locations `sqlparser` reports in the rendered SQL back to the source file
+pub struct Rendered<'a, 'i> {
+    jinja_parse: &'a JinjaParser<'i>,
+    line_starts: Vec<usize>,
+}
+
+impl<'a, 'i> Rendered<'a, 'i> {
+    pub fn new(jinja_parse: &'a JinjaParser<'i>) -> Self {
+        let output = jinja_parse.output();
+        let line_starts = std::iter::once(0)
+            .chain(output.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Rendered {
+            jinja_parse,
+            line_starts,
+        }
+    }
+
+    /// The byte offset of a 1-based line and character column in the rendered SQL
+    pub fn offset(&self, location: Location) -> Option<usize> {
+        let output = self.jinja_parse.output();
+        let line_start = *self.line_starts.get((location.line as usize).checked_sub(1)?)?;
+        let line = &output[line_start..];
+        line.char_indices()
+            .map(|(i, _)| i)
+            .chain(std::iter::once(line.len()))
+            .nth((location.column as usize).checked_sub(1)?)
+            .map(|i| line_start + i)
+    }
+
+    pub fn pest_span(&self, span: SqlSpan) -> Option<pest::Span<'i>> {
+        if span == SqlSpan::empty() {
+            return None;
+        }
+        let start = self.offset(span.start)?;
+        let end = self.offset(span.end)?;
+        self.jinja_parse.translate_span(start, end)
+    }
+
+    /// The span of a node in the source file. Nodes `sqlparser` has no location for, like literals, get `fallback`.
+    fn span(&self, span: SqlSpan, fallback: SqlSpan) -> Span {
+        match self.pest_span(span).or_else(|| self.pest_span(fallback)) {
+            Some(span) => Span::from_span(span),
+            None => Span {
+                start: FileLocation { line: 1, column: 1 },
+                end: FileLocation { line: 1, column: 1 },
+            },
+        }
+    }
 }
 
-struct ColumnSet {
-    columns: Vec<Column>,
+pub struct Column {
+    pub name: String,
+    pub span: Span,
+}
+
+pub struct ColumnSet {
+    pub columns: Vec<Column>,
+}
+
+/// A table, subquery or table function a query selects from
+pub struct Relation {
+    /// The name the query refers to it by: its alias, else the name of the table. Unaliased subqueries have none.
+    pub name: Option<String>,
+    pub span: Span,
+    /// The columns of a subquery or table function, or the ones its alias lists. Unknown for tables.
+    pub columns: Option<ColumnSet>,
 }
 
 pub struct Model {
-    name: String,
-    ctes: Option<Vec<Cte>>,
+    pub name: String,
+    pub ctes: Option<Vec<Cte>>,
+    /// The span of the final select, whose columns are the output of the model
+    pub span: Span,
+    pub columns: ColumnSet,
+    /// The relations of the final select
+    pub relations: Vec<Relation>,
 }
 
-struct Cte {
-    name: String,
-    span: Span,
-    columns: ColumnSet,
+pub struct Cte {
+    pub name: String,
+    pub name_span: Span,
+    pub span: Span,
+    pub columns: ColumnSet,
+    pub relations: Vec<Relation>,
 }
 
-// Parses an expression, not an expression w alias, and returns the expression alias in case no alias is given.
-fn parse_expression_alias(expression: Pair<Rule>) -> String {
-    if expression.as_rule() != Rule::expression {
-        panic!(
-            "parse_expression_alias only accepts expressions, not {:?}",
-            expression.as_rule()
-        );
+/// The name the warehouse gives the column of an expression without an alias
+fn column_name(expr: &Expr, dialect: Dialect) -> String {
+    match expr {
+        Expr::Identifier(ident) => ident.value.clone(),
+        Expr::CompoundIdentifier(idents) => match idents.last() {
+            Some(ident) => ident.value.clone(),
+            None => expr.to_string(),
+        },
+        Expr::Cast { expr, .. } if dialect.cast_keeps_column_name() => column_name(expr, dialect),
+        _ => expr.to_string(),
     }
-    let expr_string = expression.as_str().to_string();
-    let mut inner = expression.into_inner();
+}
 
-    let Some(inner_expression) = inner.next() else {
-        panic!("No inner expression in expression!")
-    };
-    // If we get a second inner, we have a 2ary expression
-    if let Some(_) = inner.next() {
-        return expr_string;
+fn parse_select_items(items: &[SelectItem], fallback: SqlSpan, rendered: &Rendered, dialect: Dialect) -> ColumnSet {
+    let columns = items
+        .iter()
+        .filter_map(|item| {
+            let name = match item {
+                SelectItem::UnnamedExpr(expr) => column_name(expr, dialect),
+                SelectItem::ExprWithAlias { alias, .. } => alias.value.clone(),
+                SelectItem::Wildcard(_) | SelectItem::QualifiedWildcard(..) => return None, //TODO: Handle star selects
+            };
+            Some(Column {
+                name,
+                span: rendered.span(item.span(), fallback),
+            })
+        })
+        .collect();
+
+    ColumnSet { columns }
+}
+
+fn parse_set_expr(set_expr: &SetExpr, rendered: &Rendered, dialect: Dialect) -> ColumnSet {
+    match set_expr {
+        SetExpr::Select(select) => parse_select_items(&select.projection, select.span(), rendered, dialect),
+        SetExpr::Query(query) => parse_set_expr(&query.body, rendered, dialect),
+        SetExpr::SetOperation {
+            set_quantifier,
+            left,
+            right,
+            ..
+        } => {
+            // The first query of a set operation names its columns, unless they are matched by name. Then the
+            // columns only the later queries select are added after those of the first.
+            let mut columns = parse_set_expr(left, rendered, dialect);
+            if by_name(set_quantifier) {
+                for column in parse_set_expr(right, rendered, dialect).columns {
+                    if !columns.columns.iter().any(|c| c.name.eq_ignore_ascii_case(&column.name)) {
+                        columns.columns.push(column);
+                    }
+                }
+            }
+            columns
+        }
+        _ => ColumnSet { columns: vec![] },
     }
+}
 
-    // Now that we're here, we know x is an inner expression. If it contains a qualified ident, we want to return the last part of that qualified ident.
-    let Some(x) = inner_expression.into_inner().next() else {
-        unreachable!();
-    };
-    if x.as_rule() != Rule::qualified_ident {
-        return expr_string;
+fn parse_body(body: &Body, span: SqlSpan, rendered: &Rendered, dialect: Dialect) -> ColumnSet {
+    match body {
+        Body::Query(query) => parse_set_expr(&query.body, rendered, dialect),
+        Body::Select { items, .. } => parse_select_items(items, span, rendered, dialect),
+        Body::Empty => ColumnSet { columns: vec![] },
     }
-    let qualified_ident = x;
-    let mut inner = qualified_ident.into_inner();
-    let qualifier_or_ident = inner.next().unwrap().as_str();
-    if let Some(ident) = inner.next() {
-        ident.as_str().to_string()
-    } else {
-        qualifier_or_ident.to_string()
+}
+
+/// The columns an alias like `as x (a, b)` renames the relation's columns to
+fn alias_columns(alias: &TableAlias, rendered: &Rendered) -> Option<ColumnSet> {
+    if alias.columns.is_empty() {
+        return None;
     }
+    let columns = alias
+        .columns
+        .iter()
+        .map(|column| Column {
+            name: column.name.value.clone(),
+            span: rendered.span(column.name.span, alias.name.span),
+        })
+        .collect();
+    Some(ColumnSet { columns })
 }
 
-fn parse_select_statement(pair: Pair<Rule>) -> ColumnSet {
-    let Some(select_list) = pair.into_inner().next() else {
-        panic!("select statement does not contain a selection list!");
+/// The columns of a table function from the catalog, like those of Snowflake's FLATTEN
+fn table_function_columns(name: &ObjectName, span: SqlSpan, rendered: &Rendered, dialect: Dialect) -> Option<ColumnSet> {
+    let function = functions::lookup(dialect, &name.0.last()?.value)?;
+    if function.columns.is_empty() {
+        return None;
+    }
+    // Models write the columns in lowercase, the documentation in uppercase
+    let columns = function
+        .columns
+        .iter()
+        .map(|column| Column {
+            name: column.to_lowercase(),
+            span: rendered.span(span, span),
+        })
+        .collect();
+    Some(ColumnSet { columns })
+}
+
+fn parse_table_factor(factor: &TableFactor, relations: &mut Vec<Relation>, rendered: &Rendered, dialect: Dialect) {
+    let aliased = |alias: &Option<TableAlias>, columns: Option<ColumnSet>| Relation {
+        name: alias.as_ref().map(|alias| alias.name.value.clone()),
+        span: rendered.span(factor.span(), factor.span()),
+        columns: alias.as_ref().and_then(|alias| alias_columns(alias, rendered)).or(columns),
+    };
+    let relation = match factor {
+        TableFactor::Table { name, alias, .. } => Relation {
+            name: alias
+                .as_ref()
+                .map(|alias| &alias.name)
+                .or(name.0.last())
+                .map(|ident| ident.value.clone()),
+            ..aliased(alias, None)
+        },
+        TableFactor::Derived { subquery, alias, .. } => {
+            aliased(alias, Some(parse_set_expr(&subquery.body, rendered, dialect)))
+        }
+        TableFactor::NestedJoin {
+            table_with_joins,
+            alias: None,
+        } => return parse_table_with_joins(table_with_joins, relations, rendered, dialect),
+        TableFactor::Pivot { table, alias: None, .. }
+        | TableFactor::Unpivot { table, alias: None, .. }
+        | TableFactor::MatchRecognize { table, alias: None, .. } => {
+            return parse_table_factor(table, relations, rendered, dialect)
+        }
+        TableFactor::Function { name, alias, .. } => {
+            aliased(alias, table_function_columns(name, factor.span(), rendered, dialect))
+        }
+        TableFactor::TableFunction {
+            expr: Expr::Function(function),
+            alias,
+        } => aliased(alias, table_function_columns(&function.name, factor.span(), rendered, dialect)),
+        TableFactor::TableFunction { alias, .. }
+        | TableFactor::UNNEST { alias, .. }
+        | TableFactor::JsonTable { alias, .. }
+        | TableFactor::OpenJsonTable { alias, .. }
+        | TableFactor::NestedJoin { alias, .. }
+        | TableFactor::Pivot { alias, .. }
+        | TableFactor::Unpivot { alias, .. }
+        | TableFactor::MatchRecognize { alias, .. } => aliased(alias, None),
     };
+    relations.push(relation);
+}
 
-    let columns = select_list
-        .into_inner()
-        .flat_map(|col| {
-            let span = Span::from_span(col.as_span());
-            let mut inners = col.into_inner();
-            let Some(expression) = inners.next() else {
-            panic!("column does not contain an expression");
-        };
+fn parse_table_with_joins(
+    table: &TableWithJoins,
+    relations: &mut Vec<Relation>,
+    rendered: &Rendered,
+    dialect: Dialect,
+) {
+    parse_table_factor(&table.relation, relations, rendered, dialect);
+    for join in &table.joins {
+        parse_table_factor(&join.relation, relations, rendered, dialect);
+    }
+}
 
-            if expression.as_rule() == Rule::star_select {
-                return None; //TODO: Handle star selects
-            }
+fn parse_from(from: &[TableWithJoins], relations: &mut Vec<Relation>, rendered: &Rendered, dialect: Dialect) {
+    for table in from {
+        parse_table_with_joins(table, relations, rendered, dialect);
+    }
+}
 
-            let alias = if let Some(alias) = inners.next() {
-                let Some(identifier) = alias.into_inner().next() else {
-                panic!("alias does not contain an identifier");
-            };
-                identifier.as_str().to_string()
-            } else {
-                parse_expression_alias(expression)
-            };
+/// The relations a query selects from. Those of every query in a set operation are included.
+fn parse_relations(set_expr: &SetExpr, relations: &mut Vec<Relation>, rendered: &Rendered, dialect: Dialect) {
+    match set_expr {
+        SetExpr::Select(select) => parse_from(&select.from, relations, rendered, dialect),
+        SetExpr::Query(query) => parse_relations(&query.body, relations, rendered, dialect),
+        SetExpr::SetOperation { left, right, .. } => {
+            parse_relations(left, relations, rendered, dialect);
+            parse_relations(right, relations, rendered, dialect);
+        }
+        _ => {}
+    }
+}
 
-            Some(Column { name: alias, span })
-        })
-        .collect();
+fn parse_body_relations(body: &Body, rendered: &Rendered, dialect: Dialect) -> Vec<Relation> {
+    let mut relations = vec![];
+    match body {
+        Body::Query(query) => parse_relations(&query.body, &mut relations, rendered, dialect),
+        Body::Select { from, .. } => parse_from(from, &mut relations, rendered, dialect),
+        Body::Empty => {}
+    }
+    relations
+}
 
-    ColumnSet { columns }
+/// The first construct in the body the dialect does not support, or the first set operation of queries that select
+/// different numbers of columns
+fn query_error(body: &Body, dialect: Dialect) -> Option<SyntaxError> {
+    let mut check = QueryCheck { dialect };
+    let result = match body {
+        Body::Query(query) => query.visit(&mut check),
+        Body::Select { items, from } => match items.visit(&mut check) {
+            ControlFlow::Continue(()) => from.visit(&mut check),
+            result => result,
+        },
+        Body::Empty => ControlFlow::Continue(()),
+    };
+    match result {
+        ControlFlow::Break((span, message)) => Some(SyntaxError { span, message }),
+        ControlFlow::Continue(()) => None,
+    }
 }
 
-fn parse_inner_query(pair: Pair<Rule>) -> ColumnSet {
-    debug_assert!(
-        pair.as_rule() == Rule::inner_query,
-        "parse_inner_query only accepts inner queries"
-    );
-    let Some(select_statement) = pair.into_inner().next() else {
-        panic!("inner query does not contain a select statement");
+/// The problems the dialect's catalog of functions finds in the body: warnings, and calls of unknown functions
+fn function_check(body: &Body, dialect: Dialect) -> (Vec<SyntaxError>, Vec<SyntaxError>) {
+    let mut check = FunctionCheck {
+        dialect,
+        warnings: vec![],
+        unknown_functions: vec![],
     };
+    // The check never breaks
+    match body {
+        Body::Query(query) => {
+            let _ = query.visit(&mut check);
+        }
+        Body::Select { items, from } => {
+            let _ = items.visit(&mut check);
+            let _ = from.visit(&mut check);
+        }
+        Body::Empty => {}
+    }
+    (check.warnings, check.unknown_functions)
+}
 
-    parse_select_statement(select_statement)
+/// Finds the first construct `sqlparser` accepts but the warehouse would not, like backtick identifiers outside
+/// BigQuery
+struct QueryCheck {
+    dialect: Dialect,
 }
 
-fn parse_set_operation(pair: Pair<Rule>) -> ColumnSet {
-    debug_assert!(
-        pair.as_rule() == Rule::set_operation,
-        "parse_set_operation only accepts set operations"
-    );
+type CheckError = (SqlSpan, String);
 
-    let Some(inner_query) = pair.into_inner().next() else {
-        panic!("set operation does not contain an inner query");
-    };
+impl QueryCheck {
+    fn check_idents(&self, idents: &[Ident]) -> ControlFlow<CheckError> {
+        let name = self.dialect.display_name();
+        for ident in idents {
+            let message = match ident.quote_style {
+                Some('`') if !self.dialect.backtick_identifiers() => {
+                    format!("{} quotes identifiers with double quotes, not backticks", name)
+                }
+                Some('"') if !self.dialect.double_quoted_identifiers() => {
+                    format!("{} quotes identifiers with backticks, not double quotes", name)
+                }
+                _ => continue,
+            };
+            return ControlFlow::Break((ident.span, message));
+        }
+        ControlFlow::Continue(())
+    }
 
-    parse_inner_query(inner_query)
+    fn check_set_expr(&self, set_expr: &SetExpr) -> ControlFlow<CheckError> {
+        match set_expr {
+            SetExpr::Select(select) => match &select.qualify {
+                Some(qualify) if !self.dialect.qualify() => ControlFlow::Break((
+                    qualify.span(),
+                    format!("{} does not support QUALIFY", self.dialect.display_name()),
+                )),
+                _ => ControlFlow::Continue(()),
+            },
+            SetExpr::SetOperation {
+                op,
+                set_quantifier,
+                left,
+                right,
+            } => {
+                self.check_set_expr(left)?;
+                self.check_set_expr(right)?;
+                if by_name(set_quantifier) {
+                    return ControlFlow::Continue(());
+                }
+                match (column_count(left), column_count(right)) {
+                    (Some(expected), Some(count)) if count != expected => ControlFlow::Break((
+                        right.span(),
+                        format!(
+                            "each query of a {} must select the same number of columns, this one selects {} instead of {}",
+                            op, count, expected
+                        ),
+                    )),
+                    _ => ControlFlow::Continue(()),
+                }
+            }
+            _ => ControlFlow::Continue(()),
+        }
+    }
+}
 
-    //TODO: PARSE THE REST OF THE SET OPERATION
+/// Checks the function calls of a query against the dialect's catalog of functions. The catalog need not list every
+/// function, so what it finds are warnings, and it finds all of them rather than the first.
+struct FunctionCheck {
+    dialect: Dialect,
+    /// Calls with the wrong number of arguments, and selects that mix aggregates with columns outside them
+    warnings: Vec<SyntaxError>,
+    /// Names missing from the catalog that are a typo away from a name in it
+    unknown_functions: Vec<SyntaxError>,
 }
 
-fn parse_cte(pair: Pair<Rule>) -> Cte {
-    debug_assert!(pair.as_rule() == Rule::cte, "parse_cte only accepts ctes");
-    let span = Span::from_span(pair.as_span());
-    let mut inner = pair.into_inner();
-    let Some(name) = inner.next().and_then(|x| Some(x.as_str().to_string())) else {
-        panic!("cte does not contain a name");
-    };
-    let Some(set_operation) = inner.next().and_then(|x| Some(x)) else {
-        panic!("cte does not contain a set operation");
-    };
+impl FunctionCheck {
+    /// Checks the selects of a set operation. Those of nested queries are checked when they are visited.
+    fn check_set_expr(&mut self, set_expr: &SetExpr) {
+        match set_expr {
+            SetExpr::Select(select) => self.check_aggregation(select),
+            SetExpr::SetOperation { left, right, .. } => {
+                self.check_set_expr(left);
+                self.check_set_expr(right);
+            }
+            _ => {}
+        }
+    }
 
-    let columns = parse_set_operation(set_operation);
-    Cte {
-        name,
-        span,
-        columns,
+    /// Checks that a function of the catalog is called with as many arguments as one of its signatures takes, and
+    /// whether a name missing from the catalog looks like a typo of one in it
+    fn check_function(&mut self, function: &FunctionCall, span: SqlSpan) {
+        // Qualified and quoted names are user-defined functions
+        let [name] = function.name.0.as_slice() else {
+            return;
+        };
+        if name.quote_style.is_some() {
+            return;
+        }
+        let Some(known) = functions::lookup(self.dialect, &name.value) else {
+            if let Some(suggestion) = functions::suggest(self.dialect, &name.value) {
+                self.unknown_functions.push(SyntaxError {
+                    span: name.span,
+                    message: format!("unknown function {}, did you mean {}?", name.value, suggestion.name),
+                });
+            }
+            return;
+        };
+        let count = match &function.args {
+            FunctionArguments::None => 0,
+            FunctionArguments::List(list) => list.args.len(),
+            FunctionArguments::Subquery(_) => return,
+        };
+        if !known.signatures.iter().any(|signature| signature.accepts(count)) {
+            self.warnings.push(SyntaxError {
+                span,
+                message: format!("{} takes {}, not {}", known.name, known.arity(), count),
+            });
+        }
+    }
+
+    /// Checks that a select without GROUP BY does not mix aggregate function calls with columns outside them
+    fn check_aggregation(&mut self, select: &Select) {
+        if !matches!(&select.group_by, GroupByExpr::Expressions(expressions, _) if expressions.is_empty()) {
+            return;
+        }
+        let mut aggregation = Aggregation {
+            dialect: self.dialect,
+            aliases: select
+                .projection
+                .iter()
+                .filter_map(|item| match item {
+                    SelectItem::ExprWithAlias { alias, .. } => Some(alias.value.to_lowercase()),
+                    _ => None,
+                })
+                .collect(),
+            depth: 0,
+            aggregate: None,
+            column: None,
+        };
+        let _ = select.projection.visit(&mut aggregation);
+        if let (Some(aggregate), Some((span, column))) = (aggregation.aggregate, aggregation.column) {
+            self.warnings.push(SyntaxError {
+                span,
+                message: format!(
+                    "{} is neither aggregated nor in a GROUP BY, but the select uses the aggregate function {}",
+                    column, aggregate
+                ),
+            });
+        }
     }
 }
 
-fn parse_with_clause(pair: Pair<Rule>) -> Vec<Cte> {
-    debug_assert!(
-        pair.as_rule() == Rule::with_clause,
-        "parse_with_clause only accepts with clauses"
-    );
-    let Some(ctes) = pair.into_inner().next() else {
-        panic!("with clause does not contain ctes");
-    };
-    ctes.into_inner().map(|pair| parse_cte(pair)).collect()
-}
-
-fn parse_query(pair: Pair<Rule>, name: String) -> Model {
-    debug_assert!(
-        pair.as_rule() == Rule::query,
-        "parse_query only accepts queries"
-    );
-    let mut inner = pair.into_inner();
-    let ctes = {
-        match inner.peek() {
-            Some(pair) => {
-                if pair.as_rule() == Rule::with_clause {
-                    inner.next();
-                    Some(parse_with_clause(pair))
-                } else {
-                    None
-                }
+impl Visitor for FunctionCheck {
+    type Break = ();
+
+    fn pre_visit_query(&mut self, query: &Query) -> ControlFlow<Self::Break> {
+        self.check_set_expr(&query.body);
+        ControlFlow::Continue(())
+    }
+
+    fn pre_visit_expr(&mut self, expr: &Expr) -> ControlFlow<Self::Break> {
+        if let Expr::Function(function) = expr {
+            self.check_function(function, expr.span());
+        }
+        ControlFlow::Continue(())
+    }
+}
+
+/// Finds the first aggregate function a select list calls, and the first column it uses outside of aggregate
+/// function calls and subqueries
+struct Aggregation {
+    dialect: Dialect,
+    /// The aliases of the select items, which later items can refer to instead of a column
+    aliases: Vec<String>,
+    /// How many aggregate function calls and subqueries the visited expression is in
+    depth: usize,
+    aggregate: Option<&'static str>,
+    column: Option<(SqlSpan, String)>,
+}
+
+impl Aggregation {
+    /// The aggregate function an expression calls, unless it is called as a window function
+    fn aggregate_call(&self, expr: &Expr) -> Option<&'static str> {
+        let Expr::Function(function) = expr else {
+            return None;
+        };
+        let [name] = function.name.0.as_slice() else {
+            return None;
+        };
+        let known = functions::lookup(self.dialect, &name.value)?;
+        (known.kind == FunctionKind::Aggregate && function.over.is_none()).then_some(known.name)
+    }
+}
+
+impl Visitor for Aggregation {
+    type Break = ();
+
+    fn pre_visit_query(&mut self, _query: &Query) -> ControlFlow<Self::Break> {
+        self.depth += 1;
+        ControlFlow::Continue(())
+    }
+
+    fn post_visit_query(&mut self, _query: &Query) -> ControlFlow<Self::Break> {
+        self.depth -= 1;
+        ControlFlow::Continue(())
+    }
+
+    fn pre_visit_expr(&mut self, expr: &Expr) -> ControlFlow<Self::Break> {
+        if let Some(aggregate) = self.aggregate_call(expr) {
+            if self.depth == 0 {
+                self.aggregate.get_or_insert(aggregate);
             }
-            None => None,
+            self.depth += 1;
+            return ControlFlow::Continue(());
         }
-    };
-    Model { name, ctes }
+        if self.depth > 0 || self.column.is_some() {
+            return ControlFlow::Continue(());
+        }
+        self.column = match expr {
+            Expr::Identifier(ident) if !self.aliases.contains(&ident.value.to_lowercase()) => {
+                Some((ident.span, ident.value.clone()))
+            }
+            Expr::CompoundIdentifier(_) => Some((expr.span(), expr.to_string())),
+            _ => None,
+        };
+        ControlFlow::Continue(())
+    }
+
+    fn post_visit_expr(&mut self, expr: &Expr) -> ControlFlow<Self::Break> {
+        if self.aggregate_call(expr).is_some() {
+            self.depth -= 1;
+        }
+        ControlFlow::Continue(())
+    }
+}
+
+/// Whether a set operation matches the columns of its queries by name rather than by position
+fn by_name(quantifier: &SetQuantifier) -> bool {
+    matches!(
+        quantifier,
+        SetQuantifier::ByName | SetQuantifier::AllByName | SetQuantifier::DistinctByName
+    )
+}
+
+/// The number of columns a query selects, unless it selects a wildcard
+fn column_count(set_expr: &SetExpr) -> Option<usize> {
+    match set_expr {
+        SetExpr::Select(select) => select
+            .projection
+            .iter()
+            .all(|item| !matches!(item, SelectItem::Wildcard(_) | SelectItem::QualifiedWildcard(..)))
+            .then_some(select.projection.len()),
+        SetExpr::Query(query) => column_count(&query.body),
+        SetExpr::SetOperation {
+            set_quantifier, left, ..
+        } if !by_name(set_quantifier) => column_count(left),
+        SetExpr::Values(values) => values.rows.first().map(Vec::len),
+        _ => None,
+    }
+}
+
+impl Visitor for QueryCheck {
+    type Break = CheckError;
+
+    fn pre_visit_query(&mut self, query: &Query) -> ControlFlow<Self::Break> {
+        self.check_set_expr(&query.body)
+    }
+
+    fn pre_visit_relation(&mut self, relation: &ObjectName) -> ControlFlow<Self::Break> {
+        self.check_idents(&relation.0)
+    }
+
+    fn pre_visit_expr(&mut self, expr: &Expr) -> ControlFlow<Self::Break> {
+        match expr {
+            Expr::Identifier(ident) => self.check_idents(std::slice::from_ref(ident)),
+            Expr::CompoundIdentifier(idents) => self.check_idents(idents),
+            Expr::Cast {
+                kind: CastKind::DoubleColon,
+                ..
+            } if !self.dialect.double_colon_casts() => ControlFlow::Break((
+                expr.span(),
+                format!(
+                    "{} does not support `::` casts, use CAST(... AS ...) instead",
+                    self.dialect.display_name()
+                ),
+            )),
+            _ => ControlFlow::Continue(()),
+        }
+    }
 }
 
 #[derive(Debug)]
 pub enum ErrorLoc<'i> {
-    Position(Position<'i>),
     Span(pest::Span<'i>),
     Unknown,
 }
@@ -229,65 +661,563 @@ impl<'i> SqlParseError<'i> {
     }
 }
 
-pub fn parse_sql<'i>(jinja_parse: &'i JinjaParser) -> Result<(), SqlParseError<'i>> {
-    /**/
-
-    let sql_src = jinja_parse.output().clone();
-    let sql_parse = SqlParser::parse(Rule::query, &sql_src);
-    let output = match sql_parse {
-        Ok(mut pairs) => Some(parse_query(pairs.next().unwrap(), "".into())),
-        Err(e) => {
-            match e.location {
-                pest::error::InputLocation::Pos(pos) => {
-                    let loc = jinja_parse.translate(pos);
-                    if let Some(location) = loc {
-                        return Err(SqlParseError {
-                            position: ErrorLoc::Position(location),
-                            message: format!("{:?}", e),
-                        });
-                    } else {
-                        return Err(SqlParseError {
-                            position: ErrorLoc::Unknown,
-                            message: format!("{:?}", e),
-                        });
-                    }
-                }
-                pest::error::InputLocation::Span((start, end)) => {
-                    let start = jinja_parse.translate(start);
-                    let end = jinja_parse.translate(end);
-                    if let Some(start) = start {
-                        if let Some(end) = end {
-                            let span =
-                                pest::Span::new(jinja_parse.source(), start.pos(), end.pos());
-                            return Err(SqlParseError {
-                                position: match span {
-                                    Some(span) => ErrorLoc::Span(span),
-                                    None => ErrorLoc::Unknown,
-                                },
-                                message: format!("{:?}", e),
-                            });
-                        }
-                    }
-                    return Err(SqlParseError {
-                        position: ErrorLoc::Unknown,
-                        message: format!("{:?}", e),
-                    });
-                }
+/// A model as far as it could be parsed, for completion and symbols even when it has errors, and its syntax errors
+pub struct ParseResult<'i> {
+    pub model: Model,
+    pub errors: Vec<SqlParseError<'i>>,
+    /// Function calls the dialect's catalog of functions finds wrong
+    pub warnings: Vec<SqlParseError<'i>>,
+    /// Calls of functions missing from the catalog that look like misspellings of functions in it. The catalog may
+    /// not list every function, so these can be real functions.
+    pub unknown_functions: Vec<SqlParseError<'i>>,
+}
+
+pub fn parse_sql<'i>(jinja_parse: &JinjaParser<'i>, name: &str, dialect: Dialect) -> ParseResult<'i> {
+    let rendered = Rendered::new(jinja_parse);
+    let parsed = recovery::parse_model(jinja_parse.output(), dialect);
+
+    let mut errors = parsed.errors;
+    let (mut warnings, mut unknown_functions) = (vec![], vec![]);
+    for body in parsed.ctes.iter().flatten().map(|cte| &cte.body).chain([&parsed.body]) {
+        errors.extend(query_error(body, dialect));
+        let (body_warnings, body_unknown_functions) = function_check(body, dialect);
+        warnings.extend(body_warnings);
+        unknown_functions.extend(body_unknown_functions);
+    }
+
+    let ctes = parsed.ctes.map(|ctes| {
+        ctes.iter()
+            .map(|cte| Cte {
+                name: cte.name.clone(),
+                name_span: rendered.span(cte.name_span, cte.span),
+                span: rendered.span(cte.span, cte.span),
+                columns: parse_body(&cte.body, cte.span, &rendered, dialect),
+                relations: parse_body_relations(&cte.body, &rendered, dialect),
+            })
+            .collect()
+    });
+    let model = Model {
+        name: name.into(),
+        ctes,
+        span: rendered.span(parsed.span, parsed.span),
+        columns: parse_body(&parsed.body, parsed.span, &rendered, dialect),
+        relations: parse_body_relations(&parsed.body, &rendered, dialect),
+    };
+    let located = |mut errors: Vec<SyntaxError>| -> Vec<SqlParseError<'i>> {
+        errors.sort_by_key(|error| error.span.start);
+        errors
+            .into_iter()
+            .map(|error| SqlParseError {
+                position: match rendered.pest_span(error.span) {
+                    Some(span) => ErrorLoc::Span(span),
+                    None => ErrorLoc::Unknown,
+                },
+                message: error.message,
+            })
+            .collect()
+    };
+    ParseResult {
+        model,
+        errors: located(errors),
+        warnings: located(warnings),
+        unknown_functions: located(unknown_functions),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_model_spans() {
+        let src = "with orders as (\n    select id, {{ ref('x') }}.amount as total from {{ ref('raw_orders') }}\n)\nselect id from orders\n";
+        let mut jinja_parse = JinjaParser::new(src);
+        jinja_parse.render_jinja().unwrap();
+        let result = parse_sql(&jinja_parse, "orders", Dialect::Snowflake);
+        assert!(result.errors.is_empty());
+        let model = result.model;
+
+        let ctes = model.ctes.unwrap();
+        assert_eq!(ctes.len(), 1);
+        assert_eq!(ctes[0].name, "orders");
+        assert_eq!((ctes[0].name_span.start.line, ctes[0].name_span.start.column), (1, 6));
+        let columns: Vec<&str> = ctes[0].columns.columns.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(columns, vec!["id", "total"]);
+        // The second column starts and ends at its place in the template, not the rendered output
+        let total = &ctes[0].columns.columns[1].span;
+        assert_eq!((total.start.line, total.start.column), (2, 16));
+        assert_eq!((total.end.line, total.end.column), (2, 46));
+
+        assert_eq!(model.columns.columns.len(), 1);
+        assert_eq!(model.span.start.line, 4);
+    }
+
+    fn parse(src: &str, dialect: Dialect) -> Result<Vec<String>, String> {
+        let mut jinja_parse = JinjaParser::new(src);
+        jinja_parse.render_jinja().unwrap();
+        let result = parse_sql(&jinja_parse, "", dialect);
+        match result.errors.first() {
+            None => Ok(result.model.columns.columns.into_iter().map(|c| c.name).collect()),
+            Some(e) => Err(e.message().to_string()),
+        }
+    }
+
+    fn parse_errors(src: &str) -> (ParseResult<'_>, Vec<(usize, usize, String)>) {
+        let mut jinja_parse = JinjaParser::new(src);
+        jinja_parse.render_jinja().unwrap();
+        let result = parse_sql(&jinja_parse, "", Dialect::Snowflake);
+        let errors = result
+            .errors
+            .iter()
+            .map(|e| {
+                let ErrorLoc::Span(span) = e.position() else {
+                    panic!("{} has no position", e.message());
+                };
+                let (line, column) = span.start_pos().line_col();
+                (line, column, e.message().to_string())
+            })
+            .collect();
+        (result, errors)
+    }
+
+    #[test]
+    fn test_dialects() {
+        let src = "select amount::numeric(10, 2), orders.customer.address.city, items[0] from orders qualify rn = 1";
+        assert_eq!(
+            parse(src, Dialect::Snowflake),
+            Ok(vec!["amount::NUMERIC(10,2)".into(), "city".into(), "items[0]".into()])
+        );
+        assert_eq!(
+            parse(src, Dialect::Postgres),
+            Err("Postgres does not support QUALIFY".into())
+        );
+        assert_eq!(
+            parse("select amount::int from orders", Dialect::Postgres),
+            Ok(vec!["amount".into()])
+        );
+        assert_eq!(
+            parse(src, Dialect::BigQuery),
+            Err("BigQuery does not support `::` casts, use CAST(... AS ...) instead".into())
+        );
+
+        let src = "select id, \"name\" from `project.dataset.customers`";
+        assert_eq!(parse(src, Dialect::BigQuery), Ok(vec!["id".into(), "\"name\"".into()]));
+        assert_eq!(
+            parse(src, Dialect::Snowflake),
+            Err("Snowflake quotes identifiers with double quotes, not backticks".into())
+        );
+        assert_eq!(
+            parse("select id from \"customers\"", Dialect::BigQuery),
+            Err("BigQuery quotes identifiers with backticks, not double quotes".into())
+        );
+    }
+
+    /// A relation's name and columns
+    type RelationNames<'a> = (Option<&'a str>, Option<Vec<&'a str>>);
+
+    fn relations(model: &Model) -> Vec<RelationNames<'_>> {
+        model
+            .relations
+            .iter()
+            .map(|relation| {
+                let columns = relation
+                    .columns
+                    .as_ref()
+                    .map(|columns| columns.columns.iter().map(|c| c.name.as_str()).collect());
+                (relation.name.as_deref(), columns)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_from_clauses() {
+        let cases: Vec<(&str, Vec<RelationNames>)> = vec![
+            ("select * from a, b", vec![(Some("a"), None), (Some("b"), None)]),
+            (
+                "select x.id from (select id, name as n from customers) as x",
+                vec![(Some("x"), Some(vec!["id", "n"]))],
+            ),
+            (
+                "select o.id from analytics.orders o join customers as c on o.customer_id = c.id",
+                vec![(Some("o"), None), (Some("c"), None)],
+            ),
+            (
+                "select * from orders join payments using (order_id)",
+                vec![(Some("orders"), None), (Some("payments"), None)],
+            ),
+            (
+                "select * from a cross join b full outer join c on a.id = c.id natural join d",
+                vec![(Some("a"), None), (Some("b"), None), (Some("c"), None), (Some("d"), None)],
+            ),
+            (
+                "select * from orders o left join lateral (select amount from payments p where p.order_id = o.id) l on true",
+                vec![(Some("o"), None), (Some("l"), Some(vec!["amount"]))],
+            ),
+            (
+                "select * from (select 1, 2) as t (a, b), ((select 3) union all (select 4))",
+                vec![(Some("t"), Some(vec!["a", "b"])), (None, Some(vec!["3"]))],
+            ),
+            (
+                "select * from x union all select * from y",
+                vec![(Some("x"), None), (Some("y"), None)],
+            ),
+        ];
+        for dialect in [Dialect::Snowflake, Dialect::Postgres, Dialect::Databricks, Dialect::DuckDb] {
+            for (src, expected) in &cases {
+                let mut jinja_parse = JinjaParser::new(src);
+                jinja_parse.render_jinja().unwrap();
+                let result = parse_sql(&jinja_parse, "", dialect);
+                let errors: Vec<&str> = result.errors.iter().map(|e| e.message()).collect();
+                assert!(errors.is_empty(), "{:?} in {} with {:?}", errors, src, dialect);
+                assert_eq!(&relations(&result.model), expected, "{} with {:?}", src, dialect);
             }
         }
-    };
 
-    Ok(())
+        // The relations of a select that does not parse are kept as long as its FROM clause parses
+        let (result, errors) = parse_errors("select id,, name from orders o join (select id from x) c using (id)");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            relations(&result.model),
+            vec![(Some("o"), None), (Some("c"), Some(vec!["id"]))]
+        );
+    }
+
+    #[test]
+    fn test_expressions() {
+        let src = "select
+    -amount,
+    first_name || ' ' || last_name as full_name,
+    amount::float,
+    cast(ordered_at as date),
+    true, 1.5e3, date '2024-01-01', interval '1 day',
+    amount between 1 and 10 as small,
+    exists (select 1 from refunds r where r.order_id = o.id) as refunded,
+    o.id in (select order_id from returns),
+    not is_test and (status = 'shipped' or status is null) as counted,
+    count(distinct customer_id, status),
+    coalesce(discount, 0) * -1 + 2 ^ 3 % 4,
+    case when amount > 0 then 'paid' else 'free' end as kind,
+    extract(year from ordered_at),
+    listagg(status, ',') within group (order by ordered_at) as statuses,
+    previous_status is distinct from status as changed
+from orders o
+group by all";
+        let columns = parse(src, Dialect::Snowflake).unwrap();
+        assert_eq!(
+            columns,
+            vec![
+                "-amount",
+                "full_name",
+                "amount::FLOAT",
+                "CAST(ordered_at AS DATE)",
+                "true",
+                "1.5e3",
+                "DATE '2024-01-01'",
+                "INTERVAL '1 day'",
+                "small",
+                "refunded",
+                "o.id IN (SELECT order_id FROM returns)",
+                "counted",
+                "count(DISTINCT customer_id, status)",
+                "coalesce(discount, 0) * -1 + 2 ^ 3 % 4",
+                "kind",
+                "EXTRACT(YEAR FROM ordered_at)",
+                "statuses",
+                "changed",
+            ]
+        );
+        let columns = parse(src, Dialect::Postgres).unwrap();
+        assert_eq!((columns[2].as_str(), columns[3].as_str()), ("amount", "ordered_at"));
+
+        // `is distinct from` does not start a FROM clause when the select is parsed a piece at a time
+        let (result, errors) = parse_errors("select a is distinct from b as changed,, c from t");
+        assert_eq!(errors, vec![(1, 40, "expected a select item, found ','".into())]);
+        let columns: Vec<&str> = result.model.columns.columns.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(columns, vec!["changed", "c"]);
+    }
+
+    #[test]
+    fn test_semi_structured() {
+        let src = "select
+    payload:customer.id::string as customer_id,
+    payload:items[0].sku,
+    payload['status'],
+    get(payload, 'total') as total,
+    object_construct('sku', item.value:sku, 'index', item.index) as line,
+    array_construct(1, 2) as numbers,
+    item.value:quantity::int as quantity
+from raw_orders r, lateral flatten(input => r.payload:items) item";
+        let columns = parse(src, Dialect::Snowflake).unwrap();
+        assert_eq!(
+            columns,
+            vec![
+                "customer_id",
+                "payload:items[0].sku",
+                "payload['status']",
+                "total",
+                "line",
+                "numbers",
+                "quantity"
+            ]
+        );
+
+        let flatten = (Some("item"), Some(vec!["seq", "key", "path", "index", "value", "this"]));
+        for src in [
+            src,
+            "select value from raw_orders r, table(flatten(input => r.payload:items, outer => true)) item",
+            "select item.value from raw_orders r left join lateral flatten(r.payload:items) as item",
+        ] {
+            let mut jinja_parse = JinjaParser::new(src);
+            jinja_parse.render_jinja().unwrap();
+            let result = parse_sql(&jinja_parse, "", Dialect::Snowflake);
+            assert!(result.errors.is_empty(), "{}", src);
+            assert_eq!(relations(&result.model), vec![(Some("r"), None), flatten.clone()], "{}", src);
+        }
+
+        // The alias can rename the columns of FLATTEN
+        let mut jinja_parse = JinjaParser::new("select v from t, lateral flatten(t.a) f (s, k, p, i, v, th)");
+        jinja_parse.render_jinja().unwrap();
+        let result = parse_sql(&jinja_parse, "", Dialect::Snowflake);
+        assert_eq!(relations(&result.model)[1].1, Some(vec!["s", "k", "p", "i", "v", "th"]));
+    }
+
+    #[test]
+    fn test_set_operations() {
+        let src = "select id, name from a union select id, name from b union all select id, name from c
+union distinct select 1, 'x' except select id, name from d intersect select id, name from e";
+        assert_eq!(parse(src, Dialect::Postgres), Ok(vec!["id".into(), "name".into()]));
+        assert_eq!(
+            parse("select id from a minus select id from b", Dialect::Snowflake),
+            Ok(vec!["id".into()])
+        );
+        assert_eq!(
+            parse("(select id from a) union all (select id from b) order by id limit 10", Dialect::Snowflake),
+            Ok(vec!["id".into()])
+        );
+        assert_eq!(
+            parse("select id, name from a union all by name select email, id from b", Dialect::DuckDb),
+            Ok(vec!["id".into(), "name".into(), "email".into()])
+        );
+        // Wildcards select an unknown number of columns
+        assert_eq!(
+            parse("select * from a union all select id, name from b", Dialect::Snowflake),
+            Ok(vec![])
+        );
+
+        let (_, errors) = parse_errors(
+            "with c as (
+    select id from a union select id, name from b
+)
+(select id, name from c) union all (select id from c)",
+        );
+        assert_eq!(
+            errors,
+            vec![
+                (2, 28, "each query of a UNION must select the same number of columns, this one selects 2 instead of 1".into()),
+                (4, 37, "each query of a UNION must select the same number of columns, this one selects 1 instead of 2".into()),
+            ]
+        );
+
+        // Parenthesized queries are parsed on their own when the set operation has errors
+        let (result, errors) = parse_errors("(select id,, name from a) oops union all (select id from b order) limit 10");
+        assert_eq!(
+            errors,
+            vec![
+                (1, 12, "expected a select item, found ','".into()),
+                (1, 27, "expected a set operator, found 'oops'".into()),
+                (1, 60, "expected BY after ORDER".into()),
+            ]
+        );
+        let columns: Vec<&str> = result.model.columns.columns.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(columns, vec!["id", "name"]);
+    }
+
+    #[test]
+    fn test_window_functions() {
+        let src = "select
+    id,
+    row_number() over (partition by id order by updated_at desc) as rn,
+    sum(amount) over w,
+    first_value(status) over (order by updated_at rows between unbounded preceding and current row) as first_status
+from orders
+window w as (partition by customer_id)
+qualify rn = 1";
+        for dialect in [Dialect::Snowflake, Dialect::BigQuery, Dialect::Databricks, Dialect::DuckDb] {
+            assert_eq!(
+                parse(src, dialect),
+                Ok(vec!["id".into(), "rn".into(), "sum(amount) OVER w".into(), "first_status".into()]),
+                "{:?}",
+                dialect
+            );
+        }
+        assert_eq!(
+            parse(src, Dialect::Redshift),
+            Err("Redshift does not support QUALIFY".into())
+        );
+
+        let (_, errors) = parse_errors("select row_number() over (partition id order by ts) as rn from orders qualify rn = 1");
+        assert_eq!(errors, vec![(1, 27, "expected BY after PARTITION".into())]);
+        let (_, errors) = parse_errors("select id from orders window w as partition by id");
+        assert_eq!(errors, vec![(1, 35, "expected '(', found 'partition'".into())]);
+        // An empty QUALIFY does not hide the select list
+        let (result, errors) = parse_errors("select id, rank() over (order by ts) as r from orders qualify");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(result.model.columns.columns.len(), 2);
+    }
+
+    /// Problems by line, column and message
+    type Located = Vec<(usize, usize, String)>;
+
+    /// The warnings of the catalog checks, and the calls of unknown functions
+    fn function_warnings(src: &str) -> (Located, Located) {
+        let mut jinja_parse = JinjaParser::new(src);
+        jinja_parse.render_jinja().unwrap();
+        let result = parse_sql(&jinja_parse, "", Dialect::Snowflake);
+        assert!(result.errors.is_empty(), "{}", result.errors[0].message());
+        let located = |errors: &[SqlParseError]| {
+            errors
+                .iter()
+                .map(|e| {
+                    let ErrorLoc::Span(span) = e.position() else {
+                        panic!("{} has no position", e.message());
+                    };
+                    let (line, column) = span.start_pos().line_col();
+                    (line, column, e.message().to_string())
+                })
+                .collect()
+        };
+        (located(&result.warnings), located(&result.unknown_functions))
+    }
+
+    fn warnings(src: &str) -> Vec<String> {
+        function_warnings(src).0.into_iter().map(|(_, _, message)| message).collect()
+    }
+
+    #[test]
+    fn test_function_calls() {
+        let (arity, unknown_functions) = function_warnings(
+            "with totals as (
+    select dateaad(day, 1, ordered_at) as due_at from orders
+)
+select dateadd(day, due_at), round(amount, 2, 'HALF_TO_EVEN', 1), coalesce(discount) from totals",
+        );
+        assert_eq!(
+            arity,
+            vec![
+                (4, 8, "DATEADD takes 3 arguments, not 2".into()),
+                (4, 30, "ROUND takes 1 to 3 arguments, not 4".into()),
+                (4, 67, "COALESCE takes at least 2 arguments, not 1".into()),
+            ]
+        );
+        assert_eq!(unknown_functions, vec![(2, 12, "unknown function dateaad, did you mean DATEADD?".into())]);
+        assert_eq!(warnings("select current_date(5) from orders"), ["CURRENT_DATE takes no arguments, not 1"]);
+
+        // User-defined functions, and functions of dialects without a catalog
+        let src = "select current_date, to_cents(amount) as a, analytics.dateaad(x) as b, \"DATEAAD\"(x) as c, count(*) over () as n from orders";
+        assert_eq!(function_warnings(src), (vec![], vec![]));
+        let mut jinja_parse = JinjaParser::new("select dateaad(day, 1) as d, round(1, 2, 3, 4) as r from orders");
+        jinja_parse.render_jinja().unwrap();
+        let result = parse_sql(&jinja_parse, "", Dialect::Postgres);
+        assert!(result.errors.is_empty() && result.warnings.is_empty() && result.unknown_functions.is_empty());
+
+        // A function the catalog does not list is never a warning. A name close to a catalog function is collected as
+        // an unknown function, which is only reported with the opt-in `unknown_functions` setting
+        let src = "select rpad(name, 10, '.') as padded, split_part(name, ' ', 1) as first_name from customers";
+        let (arity, unknown_functions) = function_warnings(src);
+        assert!(arity.is_empty());
+        assert_eq!(unknown_functions, vec![(1, 8, "unknown function rpad, did you mean LPAD?".into())]);
+    }
+
+    #[test]
+    fn test_aggregation() {
+        assert_eq!(
+            warnings("select customer_id, sum(amount) from orders"),
+            ["customer_id is neither aggregated nor in a GROUP BY, but the select uses the aggregate function SUM"]
+        );
+        assert_eq!(
+            warnings("select count(*), upper(o.status) from orders o"),
+            ["o.status is neither aggregated nor in a GROUP BY, but the select uses the aggregate function COUNT"]
+        );
+        // Every select is checked
+        assert_eq!(
+            warnings("with a as (select id, max(x) from t) select id, min(y) from a union all select id, 1 from b").len(),
+            2
+        );
+
+        let valid = [
+            "select customer_id, sum(amount) as total from orders group by customer_id",
+            "select customer_id, sum(amount) as total from orders group by all",
+            "select count(*) as n, sum(amount) / count(*) as average from orders",
+            // Later items can use the alias of an aggregate
+            "select sum(amount) as total, total / 100 as dollars from orders",
+            "select id, sum(amount) over (partition by customer_id) as total from orders",
+            "select count(*) as n, (select max(ordered_at) from orders) as latest from customers",
+            "select id, (select count(*) from orders o where o.customer_id = c.id) as n from customers c",
+        ];
+        for src in valid {
+            assert!(warnings(src).is_empty(), "{}", src);
+        }
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        // The position is in the template, past the Jinja expression
+        let (_, errors) = parse_errors("select {{ ref('orders') }}.id,, amount from {{ ref('orders') }}");
+        assert_eq!(errors, vec![(1, 31, "expected a select item, found ','".into())]);
+
+        let src = "select id from";
+        let (_, errors) = parse_errors(src);
+        assert_eq!(errors, vec![(1, 15, "expected identifier".into())]);
+    }
+
+    #[test]
+    fn test_error_recovery() {
+        let src = "with a as (
+    select id,, name from x
+),
+b as (
+    select id total amount from a
+),
+c as select 1,
+d as (
+    select 1 as one from a where
+)
+select id, name from b order";
+        let (result, errors) = parse_errors(src);
+        assert_eq!(
+            errors,
+            vec![
+                (2, 15, "expected a select item, found ','".into()),
+                (5, 21, "expected FROM or ',' after select item, found 'amount'".into()),
+                (7, 6, "expected '(' before the query of CTE `c`, found 'select'".into()),
+                (10, 1, "expected an expression".into()),
+                (11, 24, "expected BY after ORDER".into()),
+            ]
+        );
+
+        // What could be parsed is still there
+        let model = result.model;
+        let ctes: Vec<(&str, Vec<&str>)> = model
+            .ctes
+            .iter()
+            .flatten()
+            .map(|cte| (cte.name.as_str(), cte.columns.columns.iter().map(|c| c.name.as_str()).collect()))
+            .collect();
+        assert_eq!(ctes, vec![("a", vec!["id", "name"]), ("b", vec![]), ("d", vec!["one"])]);
+        let columns: Vec<&str> = model.columns.columns.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(columns, vec!["id", "name"]);
+    }
 }
 
-#[cfg(test)]
-mod tests {}
 #[test]
 fn test_sql_parsing() {
-    let walk_dir = WalkDir::new("./jaffle_shop/models");
+    use std::fs;
+    use walkdir::WalkDir;
+
+    let walk_dir = WalkDir::new("./test_sql/jaffa_shop/models");
 
     let entry_iterator = walk_dir.into_iter().flat_map(|x| x.ok()).flat_map(|x| {
-        let Some(file_name) = x.path().to_str() else {return None};
+        let file_name = x.path().to_str()?;
         if file_name.ends_with(".sql") {
             Some(file_name.to_string())
         } else {
@@ -312,36 +1242,19 @@ fn test_sql_parsing() {
             }
         }
 
-        let sql_src = parse_result.output().clone();
-        let res = SqlParser::parse(Rule::query, &sql_src);
-        let output = match res {
-            Ok(mut pairs) => Some(parse_query(pairs.next().unwrap(), "".into())),
-            Err(e) => {
-                println!("SQL Parsing Error: {:?}", e);
-                match e.location {
-                    pest::error::InputLocation::Pos(pos) => {
-                        let loc = parse_result.translate(pos);
-                        if let Some(location) = loc {
-                            println!("SQL Parsing Error, Location: {:?}", location);
-                            println!("Erring line: {:?}", location.line_of());
-                            println!("Location: {:?}", location.line_col());
-                        } else {
-                            println!("SQL Parsing Error, Unknown Location");
-                        }
-                        println!("SQL Parsing Error: {:?}", parse_result.translate(pos));
-                    }
-                    pest::error::InputLocation::Span((start, end)) => {
-                        println!("SQL Parsing Error: {:?}", parse_result.translate(start));
-                        println!("SQL Parsing Error: {:?}", parse_result.translate(end));
-                    }
+        let result = parse_sql(&parse_result, "", Dialect::default());
+        for e in &result.errors {
+            println!("SQL Parsing Error: {}", e.message());
+            match e.position() {
+                ErrorLoc::Span(span) => {
+                    println!("Erring line: {:?}", span.start_pos().line_of());
+                    println!("Location: {:?}", span.start_pos().line_col());
                 }
-                None
+                ErrorLoc::Unknown => println!("SQL Parsing Error, Unknown Location"),
             }
-        };
-        let Some(output) = output else {
-            println!("No model found");
-            continue;
-        };
+        }
+        assert!(result.errors.is_empty(), "Models of the example project should parse");
+        let output = result.model;
 
         if let Some(ctes) = output.ctes {
             for cte in ctes {
@@ -0,0 +1,545 @@
+use sqlparser::{
+    ast::{Query, SelectItem, SetExpr, TableWithJoins},
+    keywords::Keyword,
+    parser::{Parser, ParserError},
+    tokenizer::{Location, Span, Token, TokenWithSpan, Tokenizer},
+};
+
+use crate::dialect::Dialect;
+
+/// A syntax error, located in the rendered SQL
+#[derive(Debug)]
+pub struct SyntaxError {
+    pub span: Span,
+    pub message: String,
+}
+
+/// What could be parsed of a query: all of it, only the items it selects and the relations it selects from, or
+/// nothing
+pub enum Body {
+    Query(Box<Query>),
+    Select {
+        items: Vec<SelectItem>,
+        from: Vec<TableWithJoins>,
+    },
+    Empty,
+}
+
+pub struct ParsedCte {
+    pub name: String,
+    pub name_span: Span,
+    pub span: Span,
+    pub body: Body,
+}
+
+/// A model as far as it could be parsed, and every syntax error in it
+pub struct ParsedModel {
+    pub ctes: Option<Vec<ParsedCte>>,
+    pub body: Body,
+    /// The span of the final select
+    pub span: Span,
+    pub errors: Vec<SyntaxError>,
+}
+
+/// Keywords that start a clause after the select list
+const CLAUSES: [Keyword; 9] = [
+    Keyword::FROM,
+    Keyword::WHERE,
+    Keyword::GROUP,
+    Keyword::HAVING,
+    Keyword::QUALIFY,
+    Keyword::WINDOW,
+    Keyword::ORDER,
+    Keyword::LIMIT,
+    Keyword::OFFSET,
+];
+
+const SET_OPERATORS: [Keyword; 3] = [Keyword::UNION, Keyword::EXCEPT, Keyword::INTERSECT];
+
+fn keyword(token: &Token) -> Option<Keyword> {
+    match token {
+        Token::Word(word) if word.quote_style.is_none() && word.keyword != Keyword::NoKeyword => Some(word.keyword),
+        _ => None,
+    }
+}
+
+fn is_keyword(token: &Token, expected: Keyword) -> bool {
+    keyword(token) == Some(expected)
+}
+
+/// How a token is named in messages
+fn describe(token: &Token) -> String {
+    match token {
+        Token::EOF => "the end of the query".into(),
+        token => format!("'{}'", token),
+    }
+}
+
+/// The indices of the tokens outside of any parentheses or brackets, without whitespace. The brackets that open and
+/// close a nested part are included, so the token after an opening bracket in the list is its closing bracket.
+fn top_level(tokens: &[TokenWithSpan]) -> Vec<usize> {
+    let mut depth = 0usize;
+    let mut indices = vec![];
+    for (i, token) in tokens.iter().enumerate() {
+        match token.token {
+            Token::Whitespace(_) => {}
+            Token::LParen | Token::LBracket | Token::LBrace => {
+                if depth == 0 {
+                    indices.push(i);
+                }
+                depth += 1;
+            }
+            Token::RParen | Token::RBracket | Token::RBrace => {
+                depth = depth.saturating_sub(1);
+                if depth == 0 {
+                    indices.push(i);
+                }
+            }
+            _ if depth == 0 => indices.push(i),
+            _ => {}
+        }
+    }
+    indices
+}
+
+/// The error for an ORDER, GROUP or PARTITION at the start of the tokens that is not followed by BY
+fn missing_by(tokens: &[TokenWithSpan]) -> Option<String> {
+    let (first, rest) = tokens.split_first()?;
+    if !matches!(keyword(&first.token), Some(Keyword::ORDER | Keyword::GROUP | Keyword::PARTITION)) {
+        return None;
+    }
+    let next = rest.iter().find(|token| !matches!(token.token, Token::Whitespace(_)));
+    match next {
+        Some(token) if is_keyword(&token.token, Keyword::BY) => None,
+        _ => Some(format!("expected BY after {}", first.token.to_string().to_uppercase())),
+    }
+}
+
+/// `sqlparser` does not know MINUS, the synonym of EXCEPT, and would read it as an alias. It is one when a query
+/// follows it.
+fn minus_to_except(tokens: &mut [TokenWithSpan]) {
+    for i in 0..tokens.len() {
+        let Token::Word(word) = &tokens[i].token else {
+            continue;
+        };
+        if word.quote_style.is_some() || !word.value.eq_ignore_ascii_case("minus") {
+            continue;
+        }
+        let mut next = tokens[i + 1..]
+            .iter()
+            .filter(|token| !matches!(token.token, Token::Whitespace(_)))
+            .skip_while(|token| matches!(token.token, Token::LParen));
+        if next.next().is_some_and(|token| is_keyword(&token.token, Keyword::SELECT)) {
+            tokens[i].token = Token::make_keyword("EXCEPT");
+        }
+    }
+}
+
+fn union(tokens: &[TokenWithSpan]) -> Span {
+    Span::union_iter(tokens.iter().map(|token| token.span))
+}
+
+/// Parses a model so that one syntax error does not hide the next. Parsing resynchronizes at CTE boundaries, at the
+/// commas between select items and at clause keywords.
+pub fn parse_model(sql: &str, dialect: Dialect) -> ParsedModel {
+    let parser_dialect = dialect.parser_dialect();
+    let mut recovery = Recovery {
+        dialect: parser_dialect.as_ref(),
+        errors: vec![],
+    };
+    let tokens = match Tokenizer::new(parser_dialect.as_ref(), sql).tokenize_with_location() {
+        Ok(tokens) => tokens,
+        Err(e) => {
+            recovery.errors.push(SyntaxError {
+                span: Span::new(e.location, e.location),
+                message: e.message,
+            });
+            return ParsedModel {
+                ctes: None,
+                body: Body::Empty,
+                span: Span::empty(),
+                errors: recovery.errors,
+            };
+        }
+    };
+    let mut tokens = tokens;
+    if dialect.minus() {
+        minus_to_except(&mut tokens);
+    }
+    // Trailing semicolons end the statement, they are not part of the query
+    let mut len = tokens.len();
+    while len > 0 && matches!(tokens[len - 1].token, Token::Whitespace(_) | Token::SemiColon) {
+        len -= 1;
+    }
+    let end = tokens[..len]
+        .last()
+        .map(|token| Span::new(token.span.end, token.span.end))
+        .unwrap_or(Span::new(Location::new(1, 1), Location::new(1, 1)));
+    let (ctes, body, span) = recovery.model(&tokens[..len], end);
+    ParsedModel {
+        ctes,
+        body,
+        span,
+        errors: recovery.errors,
+    }
+}
+
+struct Recovery<'d> {
+    dialect: &'d dyn sqlparser::dialect::Dialect,
+    errors: Vec<SyntaxError>,
+}
+
+impl Recovery<'_> {
+    fn error(&mut self, span: Span, message: String) {
+        self.errors.push(SyntaxError { span, message });
+    }
+
+    /// Turns a `sqlparser` error into one with the range of the token it is about and a readable message.
+    /// `end` is where the tokens end, which is where errors about a missing token go.
+    fn syntax_error(&self, error: ParserError, tokens: &[TokenWithSpan], end: Span) -> SyntaxError {
+        let message = match error {
+            ParserError::TokenizerError(message) | ParserError::ParserError(message) => message,
+            ParserError::RecursionLimitExceeded => "the query is nested too deeply".into(),
+        };
+        let (message, location) = match message.rsplit_once(" at Line: ").and_then(|(message, location)| {
+            let (line, column) = location.split_once(", Column: ")?;
+            Some((
+                message.to_string(),
+                Location::new(line.parse().ok()?, column.parse().ok()?),
+            ))
+        }) {
+            Some((message, location)) => (message, Some(location)),
+            None => (message, None),
+        };
+        let index = location.and_then(|location| tokens.iter().position(|token| token.span.start == location));
+        let span = match (index, location) {
+            (Some(i), _) => tokens[i].span,
+            (None, Some(location)) => Span::new(location, location),
+            (None, None) => end,
+        };
+        // `partition id` fails on whatever `sqlparser` tries after PARTITION BY, which is confusing
+        if let Some(message) = index.and_then(|i| missing_by(&tokens[i..])) {
+            return SyntaxError { span, message };
+        }
+        // `Expected: an expression, found: ,` reads better as `expected an expression, found ','`
+        let message = match message
+            .strip_prefix("Expected: ")
+            .and_then(|message| message.rsplit_once(", found: "))
+        {
+            Some((expected, found)) => {
+                let expected = if expected.chars().any(char::is_alphanumeric) {
+                    expected.to_string()
+                } else {
+                    format!("'{}'", expected)
+                };
+                match found {
+                    "EOF" => format!("expected {}", expected),
+                    found => format!("expected {}, found '{}'", expected, found),
+                }
+            }
+            None => message,
+        };
+        SyntaxError { span, message }
+    }
+
+    /// Runs `parse` on the tokens, which must use all of them. `trailing` says what was expected instead of any
+    /// tokens left over.
+    fn parse<T>(
+        &self,
+        tokens: &[TokenWithSpan],
+        end: Span,
+        trailing: &str,
+        parse: impl FnOnce(&mut Parser) -> Result<T, ParserError>,
+    ) -> Result<T, SyntaxError> {
+        let mut parser = Parser::new(self.dialect).with_tokens_with_locations(tokens.to_vec());
+        let value = parse(&mut parser).map_err(|e| self.syntax_error(e, tokens, end))?;
+        let next = parser.peek_token();
+        match next.token {
+            Token::EOF => Ok(value),
+            token => Err(SyntaxError {
+                span: next.span,
+                message: format!("{}, found {}", trailing, describe(&token)),
+            }),
+        }
+    }
+
+    /// The CTEs, final select and span of the final select of a model
+    fn model(&mut self, tokens: &[TokenWithSpan], end: Span) -> (Option<Vec<ParsedCte>>, Body, Span) {
+        let top = top_level(tokens);
+        let mut n = 0;
+        let mut ctes = None;
+        if top
+            .first()
+            .is_some_and(|&i| is_keyword(&tokens[i].token, Keyword::WITH))
+        {
+            n = 1;
+            if top
+                .get(n)
+                .is_some_and(|&i| is_keyword(&tokens[i].token, Keyword::RECURSIVE))
+            {
+                n += 1;
+            }
+            let mut list = vec![];
+            loop {
+                match self.cte(tokens, &top, &mut n, end, &mut list) {
+                    Some(true) => {}
+                    Some(false) => break,
+                    // The error that made the rest unreadable is already reported
+                    None => return (Some(list), Body::Empty, end),
+                }
+            }
+            ctes = Some(list);
+        }
+
+        let Some(&start) = top.get(n) else {
+            self.error(end, "expected the final select of the model".into());
+            return (ctes, Body::Empty, end);
+        };
+        let body = &tokens[start..];
+        (ctes, self.body(body, end), union(body))
+    }
+
+    /// Parses the CTE at `top[*n]` into `ctes`. Returns whether another CTE follows, or `None` if the rest of the
+    /// model can not be made sense of.
+    fn cte(
+        &mut self,
+        tokens: &[TokenWithSpan],
+        top: &[usize],
+        n: &mut usize,
+        end: Span,
+        ctes: &mut Vec<ParsedCte>,
+    ) -> Option<bool> {
+        let token = |n: usize| top.get(n).map(|&i| &tokens[i]);
+        let found = |n: usize| {
+            token(n)
+                .map(|token| describe(&token.token))
+                .unwrap_or(describe(&Token::EOF))
+        };
+        let span = |n: usize| token(n).map(|token| token.span).unwrap_or(end);
+
+        let name = match token(*n).map(|token| &token.token) {
+            Some(Token::Word(word)) if word.keyword != Keyword::SELECT || word.quote_style.is_some() => {
+                word.value.clone()
+            }
+            _ => {
+                let message = format!("expected the name of a CTE, found {}", found(*n));
+                self.error(span(*n), message);
+                return self.resync(tokens, top, n);
+            }
+        };
+        let name_n = *n;
+        *n += 1;
+        // A list of column names
+        if token(*n).is_some_and(|token| token.token == Token::LParen)
+            && token(*n + 2).is_some_and(|token| is_keyword(&token.token, Keyword::AS))
+        {
+            *n += 2;
+        }
+        if !token(*n).is_some_and(|token| is_keyword(&token.token, Keyword::AS)) {
+            let message = format!("expected AS after the name of CTE `{}`, found {}", name, found(*n));
+            self.error(span(*n), message);
+            return self.resync(tokens, top, n);
+        }
+        *n += 1;
+        if token(*n).is_some_and(|token| is_keyword(&token.token, Keyword::NOT)) {
+            *n += 1;
+        }
+        if token(*n).is_some_and(|token| is_keyword(&token.token, Keyword::MATERIALIZED)) {
+            *n += 1;
+        }
+        if token(*n).is_none_or(|token| token.token != Token::LParen) {
+            let message = format!("expected '(' before the query of CTE `{}`, found {}", name, found(*n));
+            self.error(span(*n), message);
+            return self.resync(tokens, top, n);
+        }
+        let open = top[*n];
+        let Some(close) = token(*n + 1)
+            .filter(|token| token.token == Token::RParen)
+            .map(|_| top[*n + 1])
+        else {
+            let message = format!("expected ')' after the query of CTE `{}`", name);
+            self.error(tokens[open].span, message);
+            return None;
+        };
+        let body = self.body(&tokens[open + 1..close], tokens[close].span);
+        ctes.push(ParsedCte {
+            name,
+            name_span: tokens[top[name_n]].span,
+            span: tokens[top[name_n]].span.union(&tokens[close].span),
+            body,
+        });
+        *n += 2;
+        if token(*n).is_some_and(|token| token.token == Token::Comma) {
+            *n += 1;
+            Some(true)
+        } else {
+            Some(false)
+        }
+    }
+
+    /// Skips to the next CTE or, if there is none, the final select after an error in a CTE
+    fn resync(&mut self, tokens: &[TokenWithSpan], top: &[usize], n: &mut usize) -> Option<bool> {
+        let token = |n: usize| top.get(n).map(|&i| &tokens[i].token);
+        let next_cte = (*n..top.len()).find(|&n| {
+            token(n) == Some(&Token::Comma)
+                && matches!(token(n + 1), Some(Token::Word(_)))
+                && token(n + 2).is_some_and(|token| is_keyword(token, Keyword::AS))
+                && token(n + 3) == Some(&Token::LParen)
+        });
+        if let Some(next_cte) = next_cte {
+            *n = next_cte + 1;
+            return Some(true);
+        }
+        *n = (*n..top.len()).find(|&n| token(n).is_some_and(|token| is_keyword(token, Keyword::SELECT)))?;
+        Some(false)
+    }
+
+    /// Parses a query. If it has errors, its select list and clauses are parsed on their own so the errors in each
+    /// are found, and the items of the select list that parse are kept.
+    fn body(&mut self, tokens: &[TokenWithSpan], end: Span) -> Body {
+        let error = match self.parse(tokens, end, "expected the end of the query", |parser| {
+            parser.parse_query()
+        }) {
+            Ok(query) => return Body::Query(query),
+            Err(error) => error,
+        };
+        let errors = self.errors.len();
+        let body = self.select(tokens, end);
+        // The parts may all be fine on their own, like clauses in the wrong order
+        if self.errors.len() == errors {
+            self.errors.push(error);
+        }
+        body
+    }
+
+    /// Parses the select list and clauses of a select separately
+    fn select(&mut self, tokens: &[TokenWithSpan], end: Span) -> Body {
+        let top = top_level(tokens);
+        let Some(&select) = top.first() else {
+            return Body::Empty;
+        };
+        let span_at = |i: usize| tokens.get(i).map(|token| token.span).unwrap_or(end);
+        // A parenthesized query, which can be the first of a set operation
+        let (first, skip) = match tokens[select].token {
+            Token::LParen => match top.get(1) {
+                Some(&close) => (Some(self.body(&tokens[select + 1..close], span_at(close))), 2),
+                None => return Body::Empty,
+            },
+            _ if is_keyword(&tokens[select].token, Keyword::SELECT) => (None, 1),
+            _ => return Body::Empty,
+        };
+        let starts: Vec<usize> = top
+            .iter()
+            .enumerate()
+            .skip(skip)
+            .filter(|&(n, &i)| match keyword(&tokens[i].token) {
+                // `within group (order by ...)` is not a GROUP BY
+                Some(Keyword::GROUP) => keyword(&tokens[top[n - 1]].token) != Some(Keyword::WITHIN),
+                // Nor does `a is distinct from b` start a FROM clause
+                Some(Keyword::FROM) => keyword(&tokens[top[n - 1]].token) != Some(Keyword::DISTINCT),
+                Some(keyword) => CLAUSES.contains(&keyword) || SET_OPERATORS.contains(&keyword),
+                None => false,
+            })
+            .map(|(_, &i)| i)
+            .collect();
+
+        let list_end = starts.first().copied().unwrap_or(tokens.len());
+        let items = match first {
+            Some(_) => {
+                if let Some(&next) = top.get(skip).filter(|&&next| next < list_end) {
+                    let found = describe(&tokens[next].token);
+                    self.error(tokens[next].span, format!("expected a set operator, found {}", found));
+                }
+                vec![]
+            }
+            None => {
+                let mut list_start = select + 1;
+                if let Some(&i) = top.get(1) {
+                    if matches!(keyword(&tokens[i].token), Some(Keyword::DISTINCT | Keyword::ALL)) {
+                        list_start = i + 1;
+                    }
+                }
+                self.select_items(&tokens[list_start..list_end], span_at(list_end))
+            }
+        };
+        let mut from = vec![];
+
+        for (n, &start) in starts.iter().enumerate() {
+            if keyword(&tokens[start].token).is_some_and(|keyword| SET_OPERATORS.contains(&keyword)) {
+                // The rest is the next query of the set operation
+                let mut rest = start + 1;
+                while rest < tokens.len()
+                    && (matches!(tokens[rest].token, Token::Whitespace(_))
+                        || matches!(keyword(&tokens[rest].token), Some(Keyword::ALL | Keyword::DISTINCT)))
+                {
+                    rest += 1;
+                }
+                self.body(&tokens[rest..], end);
+                break;
+            }
+            let clause_end = starts.get(n + 1).copied().unwrap_or(tokens.len());
+            let name = tokens[start].token.to_string().to_uppercase();
+            if let Some(message) = missing_by(&tokens[start..clause_end]) {
+                self.error(tokens[start].span, message);
+                continue;
+            }
+            // A clause parses as part of `select *` with the clause's own token locations
+            let mut clause = vec![
+                TokenWithSpan::wrap(Token::make_keyword("SELECT")),
+                TokenWithSpan::wrap(Token::Mul),
+            ];
+            clause.extend_from_slice(&tokens[start..clause_end]);
+            match self.parse(
+                &clause,
+                span_at(clause_end),
+                &format!("expected the end of the {} clause", name),
+                |parser| parser.parse_query(),
+            ) {
+                Ok(query) => {
+                    if let SetExpr::Select(select) = *query.body {
+                        if is_keyword(&tokens[start].token, Keyword::FROM) {
+                            from = select.from;
+                        }
+                    }
+                }
+                Err(e) => self.errors.push(e),
+            }
+        }
+        first.unwrap_or(Body::Select { items, from })
+    }
+
+    /// Parses the items of a select list one by one
+    fn select_items(&mut self, tokens: &[TokenWithSpan], end: Span) -> Vec<SelectItem> {
+        let commas: Vec<usize> = top_level(tokens)
+            .into_iter()
+            .filter(|&i| tokens[i].token == Token::Comma)
+            .collect();
+        let mut items = vec![];
+        let mut start = 0;
+        for (n, end_index) in commas.iter().copied().chain(std::iter::once(tokens.len())).enumerate() {
+            let item = &tokens[start..end_index];
+            let item_end = tokens.get(end_index).map(|token| token.span).unwrap_or(end);
+            start = end_index + 1;
+            if item.iter().all(|token| matches!(token.token, Token::Whitespace(_))) {
+                let trailing = n == commas.len() && n > 0;
+                if trailing && self.dialect.supports_projection_trailing_commas() {
+                    continue;
+                }
+                let message = match tokens.get(end_index) {
+                    Some(token) => format!("expected a select item, found {}", describe(&token.token)),
+                    None if trailing => "expected a select item after ','".into(),
+                    None => "expected a select item".into(),
+                };
+                self.error(item_end, message);
+                continue;
+            }
+            match self.parse(item, item_end, "expected FROM or ',' after select item", |parser| {
+                parser.parse_select_item()
+            }) {
+                Ok(item) => items.push(item),
+                Err(e) => self.errors.push(e),
+            }
+        }
+        items
+    }
+}
@@ -0,0 +1,129 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use serde::Deserialize;
+use tower_lsp::lsp_types::DiagnosticSeverity;
+
+use crate::dialect::Dialect;
+
+/// How sqlfluff is run
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SqlfluffMode {
+    /// A pool of long-lived worker processes
+    Worker,
+    /// A new `sqlfluff lint` process for every lint
+    OneShot,
+}
+
+/// The severity of a diagnostic, as written in the settings
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Severity {
+    Error,
+    Warning,
+    Information,
+    Hint,
+}
+
+impl From<Severity> for DiagnosticSeverity {
+    fn from(severity: Severity) -> Self {
+        match severity {
+            Severity::Error => DiagnosticSeverity::ERROR,
+            Severity::Warning => DiagnosticSeverity::WARNING,
+            Severity::Information => DiagnosticSeverity::INFORMATION,
+            Severity::Hint => DiagnosticSeverity::HINT,
+        }
+    }
+}
+
+/// How a command linter reports what it finds
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum OutputFormat {
+    Sarif,
+    /// A list of `{"line", "column", "endLine", "endColumn", "code", "message", "severity"}` objects, of which only
+    /// the line, column and message are required
+    Json,
+}
+
+/// A linter to run on every model
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum LinterSettings {
+    Sqlfluff,
+    /// A command that reads the rendered SQL from stdin. `{file}` in the command is replaced with the model's path.
+    Command {
+        name: String,
+        command: Vec<String>,
+        format: OutputFormat,
+    },
+}
+
+/// Server settings, read from the `initializationOptions` the client sends with `initialize`
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Settings {
+    /// How long to wait after the last edit before checking a document, in milliseconds
+    pub diagnostics_debounce_ms: u64,
+    /// The SQL dialect of the project. Defaults to the adapter of the dbt profile, or Snowflake without one.
+    pub dialect: Option<Dialect>,
+    /// Report calls of functions the dialect's catalog does not list but that look like misspellings of ones it does.
+    /// Off by default, since a catalog need not list every function.
+    pub unknown_functions: bool,
+    /// The linters enabled in the workspace
+    pub linters: Vec<LinterSettings>,
+    pub sqlfluff_mode: SqlfluffMode,
+    /// The `sqlfluff` executable used in one-shot mode
+    pub sqlfluff_executable: String,
+    /// Overrides the command that starts a worker, which defaults to running the bundled worker script with Python
+    pub sqlfluff_worker_command: Option<Vec<String>>,
+    pub sqlfluff_workers: usize,
+    /// How long a worker gets to lint a file before it is restarted, in milliseconds
+    pub sqlfluff_timeout_ms: u64,
+    /// Overrides the dialect set in the project's sqlfluff config. The project's dialect is used if neither sets one.
+    pub sqlfluff_dialect: Option<String>,
+    /// A config file read on top of the project's `.sqlfluff`, `setup.cfg`, `tox.ini` and `pyproject.toml`
+    pub sqlfluff_config: Option<PathBuf>,
+    /// Extra arguments for `sqlfluff lint`. Workers do not run the command line, so setting any switches to one-shot mode.
+    pub sqlfluff_extra_args: Vec<String>,
+    /// The sqlfluff rules applied when formatting a document
+    pub formatting_rules: String,
+    /// The severity of sqlfluff violations by rule code (`LT01`) or rule group (`LT`). Violations of other rules are warnings.
+    pub sqlfluff_severities: HashMap<String, Severity>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            diagnostics_debounce_ms: 300,
+            dialect: None,
+            unknown_functions: false,
+            linters: vec![LinterSettings::Sqlfluff],
+            sqlfluff_mode: SqlfluffMode::Worker,
+            sqlfluff_executable: "sqlfluff".into(),
+            sqlfluff_worker_command: None,
+            sqlfluff_workers: 2,
+            sqlfluff_timeout_ms: 10_000,
+            sqlfluff_dialect: None,
+            sqlfluff_config: None,
+            sqlfluff_extra_args: vec![],
+            sqlfluff_severities: HashMap::new(),
+            formatting_rules: "layout,capitalisation".into(),
+        }
+    }
+}
+
+impl Settings {
+    /// Falls back to the defaults if there are no options or they can not be read
+    pub fn from_initialization_options(options: Option<serde_json::Value>) -> Settings {
+        options
+            .and_then(|options| serde_json::from_value(options).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn diagnostics_debounce(&self) -> Duration {
+        Duration::from_millis(self.diagnostics_debounce_ms)
+    }
+}
@@ -0,0 +1,250 @@
+use sqlparser::tokenizer::{Token, Tokenizer};
+use tower_lsp::lsp_types::{
+    Documentation, ParameterInformation, ParameterLabel, SignatureHelp, SignatureInformation,
+};
+
+use crate::{
+    dialect::Dialect,
+    functions::{self, Function, Signature},
+    jinja_parser::JinjaParser,
+};
+
+/// A parenthesis that is open at the cursor
+struct OpenParen {
+    /// The function called, if the parenthesis follows the name of a known function
+    function: Option<&'static Function>,
+    /// The commas at this level, which is the index of the argument the cursor is in
+    commas: usize,
+    /// The name of the argument when it is passed by name, like `mode` in `FLATTEN(input => x, mode => `
+    named: Option<String>,
+}
+
+/// The innermost call of a known function the cursor is in, with the index of the argument it is in and that
+/// argument's name, if it is passed by name
+fn enclosing_call(sql: &str, dialect: Dialect) -> Option<(&'static Function, usize, Option<String>)> {
+    let parser_dialect = dialect.parser_dialect();
+    // Only what comes before the cursor is read, so the rest of the model can be incomplete
+    let tokens = Tokenizer::new(parser_dialect.as_ref(), sql).tokenize().ok()?;
+    let tokens: Vec<&Token> = tokens
+        .iter()
+        .filter(|token| !matches!(token, Token::Whitespace(_)))
+        .collect();
+
+    let mut open: Vec<OpenParen> = vec![];
+    for (i, token) in tokens.iter().enumerate() {
+        let previous = i.checked_sub(1).map(|i| tokens[i]);
+        let previous_word = match previous {
+            Some(Token::Word(word)) if word.quote_style.is_none() => Some(word.value.as_str()),
+            _ => None,
+        };
+        match token {
+            Token::LParen => open.push(OpenParen {
+                function: previous_word.and_then(|name| functions::lookup(dialect, name)),
+                commas: 0,
+                named: None,
+            }),
+            Token::RParen => {
+                open.pop();
+            }
+            Token::Comma => {
+                if let Some(paren) = open.last_mut() {
+                    paren.commas += 1;
+                    paren.named = None;
+                }
+            }
+            Token::RArrow => {
+                if let (Some(paren), Some(name)) = (open.last_mut(), previous_word) {
+                    paren.named = Some(name.to_lowercase());
+                }
+            }
+            _ => {}
+        }
+    }
+    // A parenthesis that groups an expression is inside the argument of the call around it
+    open.into_iter()
+        .rev()
+        .find_map(|paren| Some((paren.function?, paren.commas, paren.named)))
+}
+
+/// Whether the signature can be called with the argument at `index`, or with an argument of that name
+fn accepts(signature: &Signature, index: usize, named: Option<&str>) -> bool {
+    match named {
+        Some(name) => signature.arguments.iter().any(|argument| argument.name == name),
+        None => index < signature.arguments.len() || signature.arguments.iter().any(|argument| argument.variadic),
+    }
+}
+
+/// The parameter of the signature the argument at `index` is passed to. Arguments past the last one repeat the
+/// last variadic argument.
+fn active_parameter(signature: &Signature, index: usize, named: Option<&str>) -> Option<usize> {
+    let arguments = signature.arguments;
+    match named {
+        Some(name) => arguments.iter().position(|argument| argument.name == name),
+        None if index < arguments.len() => Some(index),
+        None => arguments.iter().rposition(|argument| argument.variadic),
+    }
+}
+
+/// The offsets of each argument's placeholder in the label, like `<scale_expr>` in
+/// `ROUND( <input_expr> [ , <scale_expr> ] )`, or of its name when it is passed by name
+fn parameters(signature: &Signature) -> Vec<ParameterInformation> {
+    let label = signature.label;
+    let lowercase = label.to_ascii_lowercase();
+    let mut searched = 0;
+    signature
+        .arguments
+        .iter()
+        .map(|argument| {
+            let placeholder = format!("<{}>", argument.name.to_ascii_lowercase());
+            let found = lowercase[searched..]
+                .find(&placeholder)
+                .map(|start| (searched + start, placeholder.len()))
+                .or_else(|| {
+                    let name = argument.name.to_ascii_lowercase();
+                    lowercase[searched..].find(&name).map(|start| (searched + start, name.len()))
+                });
+            let label = match found {
+                Some((start, length)) => {
+                    searched = start + length;
+                    let utf16 = |end: usize| label[..end].encode_utf16().count() as u32;
+                    ParameterLabel::LabelOffsets([utf16(start), utf16(start + length)])
+                }
+                None => ParameterLabel::Simple(argument.name.to_string()),
+            };
+            ParameterInformation {
+                label,
+                documentation: None,
+            }
+        })
+        .collect()
+}
+
+/// The signatures of the function whose arguments the cursor is in, with the argument it is in highlighted.
+/// `offset` is a byte offset in the source of the model, which is translated to the rendered SQL. There is no help
+/// for an argument no signature takes, since clients highlight the first parameter when none is active.
+pub fn signature_help(src: &str, offset: usize, dialect: Dialect) -> Option<SignatureHelp> {
+    let mut jinja_parse = JinjaParser::new(src);
+    jinja_parse.render_jinja().ok()?;
+    let offset = jinja_parse.translate_to_output(offset)?;
+    let (function, index, named) = enclosing_call(&jinja_parse.output()[..offset], dialect)?;
+
+    let active_signature = function
+        .signatures
+        .iter()
+        .position(|signature| accepts(signature, index, named.as_deref()))?;
+    let signatures = function
+        .signatures
+        .iter()
+        .map(|signature| SignatureInformation {
+            label: signature.label.to_string(),
+            documentation: Some(Documentation::String(function.summary.to_string())),
+            parameters: Some(parameters(signature)),
+            active_parameter: active_parameter(signature, index, named.as_deref()).map(|index| index as u32),
+        })
+        .collect::<Vec<_>>();
+    let active_parameter = signatures[active_signature].active_parameter;
+    Some(SignatureHelp {
+        signatures,
+        active_signature: Some(active_signature as u32),
+        active_parameter,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The signature help at the `|` in the source: the label of the active signature, and the text of its active
+    /// parameter
+    fn help_at(src: &str, dialect: Dialect) -> Option<(String, Option<String>)> {
+        let offset = src.find('|').unwrap();
+        let src = src.replacen('|', "", 1);
+        let help = signature_help(&src, offset, dialect)?;
+        let signature = &help.signatures[help.active_signature? as usize];
+        let parameter = signature
+            .parameters
+            .as_ref()?
+            .get(help.active_parameter? as usize)
+            .map(|parameter| match &parameter.label {
+                ParameterLabel::LabelOffsets([start, end]) => signature.label[*start as usize..*end as usize].to_string(),
+                ParameterLabel::Simple(name) => name.clone(),
+            });
+        Some((signature.label.clone(), parameter))
+    }
+
+    fn active(src: &str) -> Option<String> {
+        help_at(src, Dialect::Snowflake).and_then(|(_, parameter)| parameter)
+    }
+
+    #[test]
+    fn test_active_parameter() {
+        let (label, parameter) = help_at("select dateadd(|", Dialect::Snowflake).unwrap();
+        assert_eq!(label, "DATEADD( <date_or_time_part>, <value>, <date_or_time_expr> )");
+        assert_eq!(parameter.as_deref(), Some("<date_or_time_part>"));
+        assert_eq!(active("select dateadd(day, |").as_deref(), Some("<value>"));
+        assert_eq!(active("select DateAdd(day, 1, order_date|) from orders").as_deref(), Some("<date_or_time_expr>"));
+        assert_eq!(active("select iff(amount > 0, 'paid'|, 'free')").as_deref(), Some("<expr1>"));
+
+        // Nested calls and parentheses
+        assert_eq!(active("select iff(amount > 0, round(amount, |").as_deref(), Some("<scale_expr>"));
+        assert_eq!(active("select iff(round(amount, 2) > 0, |").as_deref(), Some("<expr1>"));
+        assert_eq!(active("select iff(status in ('a', 'b'|").as_deref(), Some("<condition>"));
+        assert_eq!(active("select iff((a + b) * 2 > c, x, |").as_deref(), Some("<expr2>"));
+
+        // Repeated and named arguments
+        assert_eq!(active("select coalesce(a, b, c, |").as_deref(), Some("<expr2>"));
+        assert_eq!(active("select * from table(flatten(input => x, mode => |").as_deref(), Some("MODE"));
+
+    }
+
+    #[test]
+    fn test_no_parameter_accepts() {
+        // The client gets no signature help rather than one with the first parameter highlighted
+        for src in ["select dateadd(day, 1, x, |", "select * from table(flatten(input => x, depth => |"] {
+            let help = signature_help(&src.replace('|', ""), src.find('|').unwrap(), Dialect::Snowflake);
+            assert_eq!(serde_json::to_value(&help).unwrap(), serde_json::Value::Null);
+        }
+        // Otherwise the active parameter sent is one of the active signature's
+        let src = "select dateadd(day, 1, |";
+        let help = signature_help(&src.replace('|', ""), src.find('|').unwrap(), Dialect::Snowflake);
+        let help = serde_json::to_value(&help).unwrap();
+        assert_eq!(help["activeParameter"], 2);
+        assert_eq!(help["signatures"][0]["parameters"].as_array().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_no_signature_help() {
+        assert!(help_at("select amount| from orders", Dialect::Snowflake).is_none());
+        assert!(help_at("select round(amount) + |", Dialect::Snowflake).is_none());
+        assert!(help_at("select my_udf(|", Dialect::Snowflake).is_none());
+        assert!(help_at("select \"ROUND\"(|", Dialect::Snowflake).is_none());
+        assert!(help_at("select round(|", Dialect::BigQuery).is_none());
+    }
+
+    #[test]
+    fn test_signature_help_with_jinja() {
+        assert_eq!(
+            active("select {# the order date #} dateadd(day, 1, |) from {{ ref('orders') }}").as_deref(),
+            Some("<date_or_time_expr>")
+        );
+        assert_eq!(active("select nvl(a, b) from {{ ref('orders') }} where iff(x, |").as_deref(), Some("<expr1>"));
+        // Inside a Jinja expression there is no SQL
+        assert!(help_at("select round(amount, 2) from {{ ref('or|ders') }}", Dialect::Snowflake).is_none());
+    }
+
+    #[test]
+    fn test_active_signature() {
+        let src = "select to_timestamp(1700000000, |";
+        let help = signature_help(&src.replace('|', ""), src.find('|').unwrap(), Dialect::Snowflake).unwrap();
+        assert_eq!(help.signatures.len(), 3);
+        assert_eq!(help.active_signature, Some(0));
+        assert_eq!(help.active_parameter, Some(1));
+        let src = "select regexp_replace(subject, 'a', 'b', 1, |";
+        let help = signature_help(&src.replace('|', ""), src.find('|').unwrap(), Dialect::Snowflake).unwrap();
+        assert_eq!(help.active_parameter, Some(4));
+        assert_eq!(
+            help.signatures[0].documentation,
+            Some(Documentation::String(functions::lookup(Dialect::Snowflake, "regexp_replace").unwrap().summary.into()))
+        );
+    }
+}
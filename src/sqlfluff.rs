@@ -0,0 +1,727 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::Duration;
+
+use async_process::{Child, ChildStdin, ChildStdout, Command};
+use async_std::io::{prelude::*, BufReader};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use tower_lsp::async_trait;
+use tower_lsp::lsp_types::Url;
+
+use crate::dialect::Dialect;
+use crate::linters::{run_process, LintError, LintResult, Linter, RenderedModel};
+use crate::settings::{Settings, Severity, SqlfluffMode};
+
+/// Where the documentation of each rule lives, anchored by rule code
+const RULES_URL: &str = "https://docs.sqlfluff.com/en/stable/reference/rules.html";
+
+/// The Python script run by workers, which keeps sqlfluff loaded between lints
+const WORKER_SCRIPT: &str = include_str!("sqlfluff_worker.py");
+
+#[derive(Deserialize, Debug)]
+pub struct SqlfluffLint {
+    /// 1-based line and character in the linted (rendered) SQL. sqlfluff 3 calls these `start_line_no` and `start_line_pos`.
+    #[serde(alias = "start_line_no")]
+    pub line_no : usize,
+    #[serde(alias = "start_line_pos")]
+    pub line_pos : usize,
+    /// The exclusive end of the violation, only reported by sqlfluff 3
+    #[serde(default)]
+    pub end_line_no : Option<usize>,
+    #[serde(default)]
+    pub end_line_pos : Option<usize>,
+    pub code : String,
+    pub description : String,
+    pub name : String
+}
+
+impl SqlfluffLint {
+    /// The rule group of the code, e.g. `LT` for `LT01`
+    fn group(&self) -> &str {
+        self.code.trim_end_matches(|c: char| c.is_ascii_digit())
+    }
+
+    /// The documentation of the rule. Parsing and templating errors have codes but no rule.
+    fn documentation(&self) -> Option<Url> {
+        if matches!(self.group(), "PRS" | "TMP" | "LXR") {
+            return None;
+        }
+        Url::parse(&format!("{}#rule-{}", RULES_URL, self.code)).ok()
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[allow(dead_code)]
+pub struct SqlfluffLints {
+    pub filepath : String,
+    #[serde(rename = "violations")]
+    pub lints: Vec<SqlfluffLint>
+}
+
+/// Runs sqlfluff with `args` in `working_dir`, writes `text` to its stdin and returns what it writes to stdout.
+/// sqlfluff exits with 1 if it finds violations it did not fix, so only higher statuses are errors.
+async fn run(
+    executable : &str,
+    args : &[String],
+    working_dir : Option<&Path>,
+    text : &str,
+) -> Result<String, LintError> {
+    let output = run_process(executable, args, working_dir, text).await?;
+    if !matches!(output.status, Some(0 | 1)) {
+        return Err(LintError::Failed {
+            status: output.status,
+            stderr: output.stderr,
+        });
+    }
+    Ok(output.stdout)
+}
+
+/// Lints `text` with a new `sqlfluff lint -` process, run in `working_dir` with `args` after the fixed arguments
+pub async fn lint(
+    executable : &str,
+    args : &[String],
+    working_dir : Option<&Path>,
+    text : &str,
+) -> Result<Vec<SqlfluffLints>, LintError> {
+    let mut lint_args = vec!["lint".into(), "-".into(), "--format".into(), "json".into()];
+    lint_args.extend_from_slice(args);
+    let output = run(executable, &lint_args, working_dir, text).await?;
+    // Nothing is printed at all if the file is excluded in `.sqlfluffignore`
+    if output.trim().is_empty() {
+        return Ok(vec![]);
+    }
+    Ok(serde_json::from_str(&output)?)
+}
+
+/// Fixes `text` with a new `sqlfluff fix -` process and returns the fixed SQL
+pub async fn fix(
+    executable : &str,
+    args : &[String],
+    working_dir : Option<&Path>,
+    text : &str,
+) -> Result<String, LintError> {
+    let mut fix_args = vec!["fix".into(), "-".into()];
+    fix_args.extend_from_slice(args);
+    run(executable, &fix_args, working_dir, text).await
+}
+
+/// Whether sqlfluff finds a config file of its own in `dir` or one of its parents
+fn has_sqlfluff_config(dir: &Path) -> bool {
+    let sections = [
+        (".sqlfluff", ""),
+        ("setup.cfg", "[sqlfluff"),
+        ("tox.ini", "[sqlfluff"),
+        ("pyproject.toml", "[tool.sqlfluff"),
+    ];
+    dir.ancestors().any(|dir| {
+        sections.iter().any(|(file_name, section)| {
+            fs::read_to_string(dir.join(file_name))
+                .map(|contents| contents.contains(section))
+                .unwrap_or(false)
+        })
+    })
+}
+
+#[derive(Serialize)]
+struct WorkerRequest<'a> {
+    id: u64,
+    sql: &'a str,
+    dialect: Option<&'a str>,
+    path: Option<&'a Path>,
+    config: Option<&'a Path>,
+    rules: Option<&'a str>,
+    fix: bool,
+}
+
+/// What a lint or fix needs to know besides the SQL to find the project's config
+struct LintContext<'a> {
+    dialect: Option<&'a str>,
+    path: Option<&'a Path>,
+    config: Option<&'a Path>,
+    /// Restricts sqlfluff to these comma separated rule codes
+    rules: Option<&'a str>,
+}
+
+/// A lint answers with the sqlfluff records, a fix with the fixed SQL
+#[derive(Deserialize)]
+struct WorkerResponse {
+    id: Option<u64>,
+    result: Option<serde_json::Value>,
+    error: Option<String>,
+}
+
+struct Worker {
+    // Kept so the process is killed when the worker is dropped
+    _child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+    next_id: u64,
+}
+
+impl Worker {
+    fn spawn(command: &[String], working_dir: Option<&Path>) -> Result<Worker, LintError> {
+        let Some((program, args)) = command.split_first() else {
+            return Err(LintError::Worker("the worker command is empty".into()));
+        };
+        let mut command = Command::new(program);
+        command
+            .args(args)
+            .stdin(async_process::Stdio::piped())
+            .stdout(async_process::Stdio::piped())
+            .kill_on_drop(true);
+        if let Some(working_dir) = working_dir {
+            command.current_dir(working_dir);
+        }
+        let mut child = command.spawn()?;
+        let stdin = child.stdin.take().ok_or(LintError::CannotOpenStdin)?;
+        let stdout = child.stdout.take().ok_or(LintError::CannotOpenStdout)?;
+        Ok(Worker {
+            _child: child,
+            stdin,
+            stdout: BufReader::new(stdout),
+            next_id: 0,
+        })
+    }
+
+    async fn read_message(&mut self) -> Result<WorkerResponse, LintError> {
+        let mut length = None;
+        loop {
+            let mut line = String::new();
+            if self.stdout.read_line(&mut line).await? == 0 {
+                return Err(LintError::WorkerCrashed);
+            }
+            let line = line.trim();
+            if line.is_empty() {
+                break;
+            }
+            if let Some((key, value)) = line.split_once(':') {
+                if key.trim().eq_ignore_ascii_case("content-length") {
+                    length = value.trim().parse::<usize>().ok();
+                }
+            }
+        }
+        let Some(length) = length else {
+            return Err(LintError::WorkerCrashed);
+        };
+        let mut body = vec![0; length];
+        self.stdout.read_exact(&mut body).await?;
+        Ok(serde_json::from_slice(&body)?)
+    }
+
+    async fn request(
+        &mut self,
+        text: &str,
+        context: &LintContext<'_>,
+        fix: bool,
+    ) -> Result<serde_json::Value, LintError> {
+        self.next_id += 1;
+        let id = self.next_id;
+        let body = serde_json::to_vec(&WorkerRequest {
+            id,
+            sql: text,
+            dialect: context.dialect,
+            path: context.path,
+            config: context.config,
+            rules: context.rules,
+            fix,
+        })?;
+        self.stdin
+            .write_all(format!("Content-Length: {}\r\n\r\n", body.len()).as_bytes())
+            .await?;
+        self.stdin.write_all(&body).await?;
+        self.stdin.flush().await?;
+
+        loop {
+            let response = self.read_message().await?;
+            if response.id != Some(id) {
+                continue;
+            }
+            if let Some(error) = response.error {
+                return Err(LintError::Worker(error));
+            }
+            return response.result.ok_or(LintError::WorkerCrashed);
+        }
+    }
+}
+
+/// Kills the worker if a request is dropped or fails halfway, since its output would no longer line up with the requests
+struct InFlight<'a> {
+    worker: &'a mut Option<Worker>,
+    done: bool,
+}
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        if !self.done {
+            *self.worker = None;
+        }
+    }
+}
+
+/// Runs sqlfluff either as a pool of long-lived workers or as one process per lint.
+/// Workers are restarted when they crash, and if they can not be started at all the pool falls back to one process per lint.
+/// Both run in the workspace root and are told the path of the file, so sqlfluff picks up the project's config files.
+pub struct Sqlfluff {
+    mode: SqlfluffMode,
+    executable: String,
+    worker_command: Vec<String>,
+    timeout: Duration,
+    working_dir: Option<PathBuf>,
+    dialect: Option<String>,
+    /// The dialect of the project, for files no sqlfluff config sets a dialect for
+    project_dialect: Dialect,
+    config: Option<PathBuf>,
+    extra_args: Vec<String>,
+    severities: HashMap<String, Severity>,
+    workers: Vec<Mutex<Option<Worker>>>,
+    next_worker: AtomicUsize,
+    workers_failed: AtomicBool,
+}
+
+impl Default for Sqlfluff {
+    fn default() -> Self {
+        Sqlfluff::new(&Settings::default(), None, Dialect::default())
+    }
+}
+
+impl Sqlfluff {
+    pub fn new(settings: &Settings, working_dir: Option<&Path>, project_dialect: Dialect) -> Sqlfluff {
+        let worker_command = settings.sqlfluff_worker_command.clone().unwrap_or_else(|| {
+            let python = if cfg!(windows) { "python" } else { "python3" };
+            vec![python.into(), "-c".into(), WORKER_SCRIPT.into()]
+        });
+        // Workers can not pass arbitrary arguments to sqlfluff
+        let mode = if settings.sqlfluff_extra_args.is_empty() {
+            settings.sqlfluff_mode
+        } else {
+            SqlfluffMode::OneShot
+        };
+        Sqlfluff {
+            mode,
+            executable: settings.sqlfluff_executable.clone(),
+            worker_command,
+            timeout: Duration::from_millis(settings.sqlfluff_timeout_ms),
+            working_dir: working_dir.map(Path::to_path_buf),
+            dialect: settings.sqlfluff_dialect.clone(),
+            project_dialect,
+            config: settings.sqlfluff_config.clone(),
+            extra_args: settings.sqlfluff_extra_args.clone(),
+            severities: settings.sqlfluff_severities.clone(),
+            workers: (0..settings.sqlfluff_workers.max(1))
+                .map(|_| Mutex::new(None))
+                .collect(),
+            next_worker: AtomicUsize::new(0),
+            workers_failed: AtomicBool::new(false),
+        }
+    }
+
+    fn context<'a>(&'a self, dialect: &'a Option<String>, path: Option<&'a Path>, rules: Option<&'a str>) -> LintContext<'a> {
+        LintContext {
+            dialect: dialect.as_deref(),
+            path,
+            config: self.config.as_deref(),
+            rules,
+        }
+    }
+
+    fn use_one_shot(&self) -> bool {
+        self.mode == SqlfluffMode::OneShot || self.workers_failed.load(Ordering::Relaxed)
+    }
+
+    /// Whether a worker request failed because workers can not run at all, in which case this and every later
+    /// request is handed to a new sqlfluff process instead. Timeouts and errors reported by sqlfluff do not count.
+    fn workers_failed<T>(&self, result: &Result<T, LintError>) -> bool {
+        let failed = matches!(result, Err(e) if !matches!(e, LintError::WorkerTimeout | LintError::Worker(_)));
+        if failed {
+            self.workers_failed.store(true, Ordering::Relaxed);
+        }
+        failed
+    }
+
+    /// Lints the rendered SQL of the file at `path`. sqlfluff reports the violations per file, and it sees none if the
+    /// file is ignored.
+    pub async fn lint_sql(&self, text: &str, path: Option<&Path>) -> Result<Vec<SqlfluffLints>, LintError> {
+        let dialect = self.dialect(path);
+        let context = self.context(&dialect, path, None);
+        if self.use_one_shot() {
+            return self.lint_one_shot(text, &context).await;
+        }
+        let result = self
+            .request_worker(text, &context, false)
+            .await
+            .and_then(|result| Ok(serde_json::from_value::<Vec<SqlfluffLints>>(result)?));
+        if self.workers_failed(&result) {
+            return self.lint_one_shot(text, &context).await;
+        }
+        result
+    }
+
+    /// Fixes the rendered SQL of the file at `path`, only applying the comma separated `rules` if given
+    pub async fn fix(&self, text: &str, path: Option<&Path>, rules: Option<&str>) -> Result<String, LintError> {
+        let dialect = self.dialect(path);
+        let context = self.context(&dialect, path, rules);
+        if self.use_one_shot() {
+            return self.fix_one_shot(text, &context).await;
+        }
+        let result = self
+            .request_worker(text, &context, true)
+            .await
+            .and_then(|result| Ok(serde_json::from_value::<String>(result)?));
+        if self.workers_failed(&result) {
+            return self.fix_one_shot(text, &context).await;
+        }
+        result
+    }
+
+    /// The violations as lint results, with their severity looked up by rule code and then by rule group
+    fn lint_results(&self, files: &[SqlfluffLints]) -> Vec<LintResult> {
+        files
+            .iter()
+            .flat_map(|file| file.lints.iter())
+            .map(|lint| LintResult {
+                line: lint.line_no,
+                column: lint.line_pos,
+                end: lint.end_line_no.zip(lint.end_line_pos),
+                code: Some(lint.code.clone()),
+                message: format!("{} ({})", lint.description, lint.name),
+                severity: self
+                    .severities
+                    .get(&lint.code)
+                    .or_else(|| self.severities.get(lint.group()))
+                    .copied()
+                    .unwrap_or(Severity::Warning),
+                documentation: lint.documentation(),
+            })
+            .collect()
+    }
+
+    /// The dialect sqlfluff is told to use. Without a configured one the project's sqlfluff config decides, and only
+    /// if there is none the project's dialect is used, since sqlfluff refuses to lint without a dialect.
+    fn dialect(&self, path: Option<&Path>) -> Option<String> {
+        if self.dialect.is_some() {
+            return self.dialect.clone();
+        }
+        let dir = path.and_then(Path::parent).or(self.working_dir.as_deref());
+        if self.config.is_some() || dir.is_some_and(has_sqlfluff_config) {
+            return None;
+        }
+        Some(self.project_dialect.sqlfluff_name().into())
+    }
+
+    fn one_shot_args(&self, context: &LintContext) -> Vec<String> {
+        let mut args = vec![];
+        if let Some(dialect) = context.dialect {
+            args.extend(["--dialect".into(), dialect.into()]);
+        }
+        if let Some(config) = context.config {
+            args.extend(["--config".into(), config.display().to_string()]);
+        }
+        if let Some(path) = context.path {
+            args.extend(["--stdin-filename".into(), path.display().to_string()]);
+        }
+        // The SQL is already rendered, so a dbt or jinja templater in the project's config must not run again
+        args.extend(["--templater".into(), "raw".into()]);
+        args.extend(self.extra_args.iter().cloned());
+        if let Some(rules) = context.rules {
+            args.extend(["--rules".into(), rules.into()]);
+        }
+        args
+    }
+
+    async fn lint_one_shot(&self, text: &str, context: &LintContext<'_>) -> Result<Vec<SqlfluffLints>, LintError> {
+        let args = self.one_shot_args(context);
+        lint(&self.executable, &args, self.working_dir.as_deref(), text).await
+    }
+
+    async fn fix_one_shot(&self, text: &str, context: &LintContext<'_>) -> Result<String, LintError> {
+        let args = self.one_shot_args(context);
+        fix(&self.executable, &args, self.working_dir.as_deref(), text).await
+    }
+
+    async fn request_worker(
+        &self,
+        text: &str,
+        context: &LintContext<'_>,
+        fix: bool,
+    ) -> Result<serde_json::Value, LintError> {
+        let index = self.next_worker.fetch_add(1, Ordering::Relaxed) % self.workers.len();
+        let mut worker = self.workers[index].lock().await;
+
+        // A worker that died since the last lint gets one restart
+        let mut result = Err(LintError::WorkerCrashed);
+        for _ in 0..2 {
+            if worker.is_none() {
+                *worker = Some(Worker::spawn(&self.worker_command, self.working_dir.as_deref())?);
+            }
+            let mut in_flight = InFlight {
+                worker: &mut worker,
+                done: false,
+            };
+            let running = in_flight.worker.as_mut().expect("the worker was just started");
+            result = match tokio::time::timeout(self.timeout, running.request(text, context, fix)).await {
+                Ok(result) => result,
+                Err(_) => Err(LintError::WorkerTimeout),
+            };
+            // An error response leaves the worker in step with the requests, anything else does not
+            in_flight.done = matches!(result, Ok(_) | Err(LintError::Worker(_)));
+            match result {
+                Err(LintError::WorkerCrashed) | Err(LintError::Io(_)) => continue,
+                _ => break,
+            }
+        }
+        result
+    }
+}
+
+#[async_trait]
+impl Linter for Sqlfluff {
+    fn name(&self) -> &str {
+        "sqlfluff"
+    }
+
+    async fn lint(&self, model: &RenderedModel<'_>) -> Result<Vec<LintResult>, LintError> {
+        let files = self.lint_sql(model.sql, model.path).await?;
+        Ok(self.lint_results(&files))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Speaks the worker protocol without sqlfluff. Replies with its pid, hangs on "hang", answers "fail" with an error
+    /// and exits after the first reply when started with `--once`.
+    const FAKE_WORKER: &str = r#"
+import json, os, sys, time
+
+def read():
+    headers = {}
+    while True:
+        line = sys.stdin.buffer.readline()
+        if not line:
+            sys.exit(0)
+        line = line.strip()
+        if not line:
+            break
+        key, value = line.split(b":", 1)
+        headers[key.lower()] = int(value)
+    return json.loads(sys.stdin.buffer.read(headers[b"content-length"]))
+
+def write(message):
+    body = json.dumps(message).encode()
+    sys.stdout.buffer.write(b"Content-Length: %d\r\n\r\n" % len(body) + body)
+    sys.stdout.buffer.flush()
+
+while True:
+    request = read()
+    if request["sql"] == "hang":
+        time.sleep(60)
+    if request["sql"] == "fail":
+        write({"id": request["id"], "error": "cannot lint"})
+        continue
+    if request["fix"]:
+        write({"id": request["id"], "result": "%s -- fixed %s" % (request["sql"], request["rules"])})
+        continue
+    # The name echoes what the worker was told about the file
+    name = "%s:%s" % (request["dialect"], request["path"])
+    violation = {"line_no": 1, "line_pos": 1, "code": "LT01", "description": str(os.getpid()), "name": name}
+    write({"id": request["id"], "result": [{"filepath": "stdin", "violations": [violation]}]})
+    if "--once" in sys.argv:
+        sys.exit(0)
+"#;
+
+    fn fake_sqlfluff(args: &[&str], timeout_ms: u64) -> Sqlfluff {
+        let mut command = vec!["python3".to_string(), "-c".into(), FAKE_WORKER.into()];
+        command.extend(args.iter().map(|arg| arg.to_string()));
+        Sqlfluff::new(
+            &Settings {
+                sqlfluff_worker_command: Some(command),
+                sqlfluff_workers: 1,
+                sqlfluff_timeout_ms: timeout_ms,
+                sqlfluff_executable: "/nonexistent/sqlfluff".into(),
+                ..Settings::default()
+            },
+            None,
+            Dialect::default(),
+        )
+    }
+
+    async fn worker_pid(sqlfluff: &Sqlfluff, text: &str) -> String {
+        let files = sqlfluff.lint_sql(text, None).await.unwrap();
+        files[0].lints[0].description.clone()
+    }
+
+    #[tokio::test]
+    async fn test_project_config() {
+        let project = Path::new("test_sql/jaffa_shop");
+        let model = project.join("models/customers.sql");
+        let sqlfluff = Sqlfluff::new(&Settings::default(), Some(project), Dialect::BigQuery);
+        // The project's .sqlfluff sets the dialect, elsewhere it falls back to the project's dialect
+        assert_eq!(sqlfluff.dialect(Some(&model)), None);
+        assert_eq!(sqlfluff.dialect(Some(Path::new("src/main.rs"))), Some("bigquery".into()));
+
+        let context = LintContext {
+            dialect: None,
+            path: Some(&model),
+            config: Some(Path::new("lint.cfg")),
+            rules: Some("LT01,CP01"),
+        };
+        assert_eq!(
+            sqlfluff.one_shot_args(&context),
+            [
+                "--config",
+                "lint.cfg",
+                "--stdin-filename",
+                "test_sql/jaffa_shop/models/customers.sql",
+                "--templater",
+                "raw",
+                "--rules",
+                "LT01,CP01"
+            ]
+        );
+
+        let worker = fake_sqlfluff(&[], 5_000);
+        let files = worker.lint_sql("select 1", Some(&model)).await.unwrap();
+        assert_eq!(files[0].lints[0].name, "None:test_sql/jaffa_shop/models/customers.sql");
+
+        // Extra arguments can only be passed on the command line
+        let settings = Settings {
+            sqlfluff_dialect: Some("bigquery".into()),
+            sqlfluff_extra_args: vec!["--rules".into(), "LT01".into()],
+            ..Settings::default()
+        };
+        let sqlfluff = Sqlfluff::new(&settings, Some(project), Dialect::Postgres);
+        assert_eq!(sqlfluff.mode, SqlfluffMode::OneShot);
+        assert_eq!(sqlfluff.dialect(Some(&model)), Some("bigquery".into()));
+    }
+
+    #[test]
+    fn test_lint_results() {
+        let files: Vec<SqlfluffLints> = serde_json::from_str(
+            r#"[{"filepath": "stdin", "violations": [
+                {"line_no": 1, "line_pos": 1, "code": "CP01", "description": "Keywords must be consistently upper case.", "name": "capitalisation.keywords"},
+                {"start_line_no": 1, "start_line_pos": 8, "end_line_no": 1, "end_line_pos": 10, "code": "LT01", "description": "Expected single whitespace.", "name": "layout.spacing"},
+                {"line_no": 1, "line_pos": 10, "code": "AL05", "description": "Alias is unused.", "name": "aliasing.unused"},
+                {"line_no": 1, "line_pos": 1, "code": "PRS", "description": "Line 1, Position 1: Found unparsable section", "name": "parsing"}
+            ]}]"#,
+        )
+        .unwrap();
+        let settings = Settings {
+            sqlfluff_severities: HashMap::from([
+                ("LT".into(), Severity::Hint),
+                ("AL05".into(), Severity::Information),
+                ("PRS".into(), Severity::Error),
+            ]),
+            ..Settings::default()
+        };
+        let results = Sqlfluff::new(&settings, None, Dialect::default()).lint_results(&files);
+
+        let severities: Vec<_> = results.iter().map(|result| result.severity).collect();
+        assert_eq!(
+            severities,
+            [Severity::Warning, Severity::Hint, Severity::Information, Severity::Error]
+        );
+        let keywords = &results[0];
+        assert_eq!(keywords.code.as_deref(), Some("CP01"));
+        assert_eq!(
+            keywords.message,
+            "Keywords must be consistently upper case. (capitalisation.keywords)"
+        );
+        assert_eq!(
+            keywords.documentation.as_ref().unwrap().as_str(),
+            "https://docs.sqlfluff.com/en/stable/reference/rules.html#rule-CP01"
+        );
+        // sqlfluff 3 names the start differently and also reports the end
+        assert_eq!((results[1].line, results[1].column, results[1].end), (1, 8, Some((1, 10))));
+        assert!(results[3].documentation.is_none());
+    }
+
+    /// A stand-in for the sqlfluff command line that prints `output` and exits with `status`
+    #[cfg(unix)]
+    fn fake_executable(name: &str, output: &str, status: i32) -> Sqlfluff {
+        use std::os::unix::fs::PermissionsExt;
+
+        let script = format!(
+            "#!/usr/bin/env python3\nimport sys\nsys.stdin.read()\nprint({:?})\nprint('Traceback:\\nValueError: no dialect', file=sys.stderr)\nsys.exit({})\n",
+            output, status
+        );
+        let executable = std::env::temp_dir().join(format!("fake-sqlfluff-{}-{}", std::process::id(), name));
+        fs::write(&executable, script).unwrap();
+        fs::set_permissions(&executable, fs::Permissions::from_mode(0o755)).unwrap();
+        Sqlfluff::new(
+            &Settings {
+                sqlfluff_mode: SqlfluffMode::OneShot,
+                sqlfluff_executable: executable.display().to_string(),
+                ..Settings::default()
+            },
+            None,
+            Dialect::default(),
+        )
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_one_shot_output() {
+        let violations = r#"[{"filepath": "a.sql", "violations": []}, {"filepath": "b.sql", "violations": [{"line_no": 1, "line_pos": 1, "code": "LT01", "description": "", "name": ""}]}]"#;
+        let files = fake_executable("files", violations, 1).lint_sql("select 1", None).await.unwrap();
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[1].lints[0].code, "LT01");
+
+        // An ignored file prints nothing
+        let ignored = fake_executable("ignored", "", 0);
+        assert!(ignored.lint_sql("select 1", None).await.unwrap().is_empty());
+
+        let error = fake_executable("json", "[{", 1).lint_sql("select 1", None).await.unwrap_err();
+        assert!(matches!(error, LintError::Json(_)));
+
+        let error = fake_executable("status", "", 2).lint_sql("select 1", None).await.unwrap_err();
+        assert_eq!(error.to_string(), "exited with status 2: ValueError: no dialect");
+    }
+
+    #[tokio::test]
+    async fn test_worker_is_reused() {
+        let sqlfluff = fake_sqlfluff(&[], 5_000);
+        let pid = worker_pid(&sqlfluff, "select 1").await;
+        assert!(matches!(sqlfluff.lint_sql("fail", None).await, Err(LintError::Worker(_))));
+        assert_eq!(worker_pid(&sqlfluff, "select 2").await, pid);
+        // Fixes go to the same worker
+        let fixed = sqlfluff.fix("select 3", None, Some("LT01")).await.unwrap();
+        assert_eq!(fixed, "select 3 -- fixed LT01");
+        assert_eq!(worker_pid(&sqlfluff, "select 4").await, pid);
+    }
+
+    #[tokio::test]
+    async fn test_worker_restarts_after_crash() {
+        let sqlfluff = fake_sqlfluff(&["--once"], 5_000);
+        let pid = worker_pid(&sqlfluff, "select 1").await;
+        assert_ne!(worker_pid(&sqlfluff, "select 2").await, pid);
+        assert!(!sqlfluff.workers_failed.load(Ordering::Relaxed));
+    }
+
+    #[tokio::test]
+    async fn test_worker_timeout() {
+        let sqlfluff = fake_sqlfluff(&[], 500);
+        let pid = worker_pid(&sqlfluff, "select 1").await;
+        assert!(matches!(sqlfluff.lint_sql("hang", None).await, Err(LintError::WorkerTimeout)));
+        // The hanging worker was killed and replaced
+        assert_ne!(worker_pid(&sqlfluff, "select 1").await, pid);
+    }
+
+    #[tokio::test]
+    async fn test_falls_back_to_one_shot() {
+        let settings = Settings {
+            sqlfluff_worker_command: Some(vec!["false".into()]),
+            sqlfluff_executable: "/nonexistent/sqlfluff".into(),
+            ..Settings::default()
+        };
+        let sqlfluff = Sqlfluff::new(&settings, None, Dialect::default());
+        // The worker exits right away, so the lint is handed to the (missing) sqlfluff executable
+        let error = sqlfluff.lint_sql("select 1", None).await.unwrap_err();
+        assert!(matches!(error, LintError::NotFound(_)));
+        assert_eq!(error.to_string(), "`/nonexistent/sqlfluff` was not found");
+        assert!(sqlfluff.workers_failed.load(Ordering::Relaxed));
+    }
+}
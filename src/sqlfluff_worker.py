@@ -0,0 +1,78 @@
+"""A long-lived sqlfluff process that lints SQL sent to it over stdin.
+
+Requests and responses are JSON objects framed with a Content-Length header, like the language server protocol.
+A request is {"id": 1, "sql": "select 1", "dialect": "snowflake", "path": "models/a.sql", "config": null, "rules": null,
+"fix": false}, and the response is {"id": 1, "result": [...]} with the same records as `sqlfluff lint --format json`,
+or {"id": 1, "error": "..."}. With "fix" set, the result is the fixed SQL like `sqlfluff fix -` prints it.
+The dialect, path, config file and rules are optional, and the config is looked up from the directory of the path like
+`sqlfluff lint --stdin-filename` does.
+"""
+import json
+import os
+import sys
+
+from sqlfluff.core import FluffConfig, Linter
+
+
+def read_message(stream):
+    headers = {}
+    while True:
+        line = stream.readline()
+        if not line:
+            return None
+        line = line.strip()
+        if not line:
+            break
+        key, value = line.split(b":", 1)
+        headers[key.strip().lower()] = value.strip()
+    length = int(headers[b"content-length"])
+    return json.loads(stream.read(length))
+
+
+def write_message(stream, message):
+    body = json.dumps(message).encode("utf-8")
+    stream.write(b"Content-Length: %d\r\n\r\n" % len(body))
+    stream.write(body)
+    stream.flush()
+
+
+def lint(request):
+    path = request.get("path")
+    # The SQL is already rendered, so a dbt or jinja templater in the project's config must not run again
+    overrides = {"templater": "raw"}
+    if request.get("dialect"):
+        overrides["dialect"] = request["dialect"]
+    if request.get("rules"):
+        overrides["rules"] = request["rules"]
+    # The config is read for every request so edits to the project's config files apply right away
+    config = FluffConfig.from_path(
+        os.path.dirname(os.path.abspath(path)) if path else os.getcwd(),
+        extra_config_path=request.get("config"),
+        overrides=overrides,
+    )
+    linter = Linter(config=config)
+    if request.get("fix"):
+        fixed, _ = linter.lint_string(request["sql"], fname=path or "stdin", fix=True).fix_string()
+        return fixed
+    result = linter.lint_string_wrapped(request["sql"], fname=path or "stdin")
+    return result.as_records()
+
+
+def main():
+    stdin = sys.stdin.buffer
+    stdout = sys.stdout.buffer
+    # Anything sqlfluff prints must not end up in the responses
+    sys.stdout = sys.stderr
+    while True:
+        request = read_message(stdin)
+        if request is None:
+            return
+        try:
+            response = {"id": request["id"], "result": lint(request)}
+        except Exception as e:
+            response = {"id": request.get("id"), "error": str(e)}
+        write_message(stdout, response)
+
+
+if __name__ == "__main__":
+    main()
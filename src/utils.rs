@@ -1,5 +1,7 @@
 use std::fmt::{Display, Formatter};
 
+use tower_lsp::lsp_types::{Position, Range};
+
 #[derive(Debug)]
 pub struct FileLocation {
     pub line: usize,
@@ -17,3 +19,22 @@ pub struct Span {
     pub start: FileLocation,
     pub end: FileLocation,
 }
+
+impl FileLocation {
+    /// Converts a 1-indexed location to a 0-indexed LSP position
+    pub fn to_position(&self) -> Position {
+        Position {
+            line: self.line.saturating_sub(1) as u32,
+            character: self.column.saturating_sub(1) as u32,
+        }
+    }
+}
+
+impl Span {
+    pub fn to_range(&self) -> Range {
+        Range {
+            start: self.start.to_position(),
+            end: self.end.to_position(),
+        }
+    }
+}
@@ -1,86 +1,422 @@
-use std::future;
-
-use tl::{ParserOptions, VDom};
-
-///Translate definition to pest
-fn translate_definition(definition : &str, function_name : &str) -> String {
-    let mut res = String::new();
-
-    res.push_str(&format!("{} = ", function_name.to_lowercase()));
-
-    return res;
-}
-
-async fn parse_http<T>(path : &str, callback : impl Fn(VDom) -> T) -> T {
-    let result = reqwest::get(path).await;
-
-    let result = result.unwrap();
-    let text = result.text().await.unwrap();
-    let parsed = tl::parse(&text, ParserOptions::default()).unwrap();
-
-    callback(parsed)
-}
-
-async fn get_function_list(path : &str) -> Vec<String> {
-
-    parse_http(path, |parsed| {
-        let doc_elements = parsed.get_elements_by_class_name("line");
-        doc_elements.into_iter().flat_map(|doc_element| {
-            let node = doc_element.get(parsed.parser());
-            let Some(inner) = node else {
-                return vec![]
-            };
-            let function_line = inner.inner_text(parsed.parser());
-            let functions = function_line.split(",");
-            functions.map( |function| {
-                function.trim().to_string()
-            }).collect()
-        }).collect()
-    
-    }).await
-}
-
-async fn get_aggregate_function_definition(path : &str) -> Vec<String> {
-    parse_http(path, |parsed| {
-        let doc_elements = parsed.get_elements_by_class_name("highlight-sqlsyntax");
-
-        doc_elements.into_iter().flat_map(|doc_element| {
-            let node = doc_element.get(parsed.parser());
-            let Some(inner) = node else {
-                return None
-            };
-            let function_line = inner.inner_text(parsed.parser());
-            
-            Some(function_line.trim().to_string())
-        }).collect()
-    }).await
-}
-
-#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
-async fn webscrape() {
-    let aggregate_functions = get_function_list("https://docs.snowflake.com/en/sql-reference/functions-aggregation").await;
-    let aggregate_function_links = aggregate_functions.iter().map(|function| {
-        format!("https://docs.snowflake.com/en/sql-reference/functions/{}", function.to_lowercase())
-    }).collect::<Vec<String>>();
-    let function_definitions = futures::future::join_all(aggregate_function_links.iter().map(|function| {
-        get_aggregate_function_definition(function)
-    })).await;
-
-    for (i, function_definition) in function_definitions.iter().enumerate() {
-        println!("-- Looking at function {} --", aggregate_functions[i]);
-        if function_definition.len() == 0 {
-            println!("No definition for function {} - {:?} with link {}", i, aggregate_functions[i], aggregate_function_links[i]);
-            continue;
+//! Builds the Snowflake function catalog from the SQL reference, either live or from saved copies of its pages
+
+use std::{
+    collections::HashSet,
+    fmt::{Display, Formatter},
+    fs, io,
+    path::PathBuf,
+    time::Duration,
+};
+
+use futures::{stream, StreamExt};
+use tl::{NodeHandle, ParserOptions, VDom};
+
+use crate::{
+    dialect::Dialect,
+    functions::catalog::{Catalog, CatalogFunction, CATALOG_VERSION},
+};
+
+mod signature;
+
+use signature::parse_signature;
+
+/// The pages of the SQL reference that list the functions of each category, by the category they are cataloged as.
+/// A function listed in several categories, like SUM, gets the first.
+const CATEGORIES: [(&str, &str); 10] = [
+    ("aggregate", "functions-aggregation"),
+    ("window", "functions-window"),
+    ("table", "functions-table"),
+    ("conditional", "functions-conditional"),
+    ("context", "functions-context"),
+    ("conversion", "functions-conversion"),
+    ("date", "functions-date-time"),
+    ("numeric", "functions-numeric"),
+    ("semi-structured", "functions-semistructured"),
+    ("string", "functions-string"),
+];
+
+/// The data types a summary of what a function returns is searched for. Longer names come before their prefixes.
+const DATA_TYPES: [&str; 19] = [
+    "TIMESTAMP_NTZ",
+    "TIMESTAMP_LTZ",
+    "TIMESTAMP_TZ",
+    "TIMESTAMP",
+    "NUMBER",
+    "INTEGER",
+    "FLOAT",
+    "DOUBLE",
+    "VARCHAR",
+    "STRING",
+    "BOOLEAN",
+    "DATE",
+    "TIME",
+    "VARIANT",
+    "ARRAY",
+    "OBJECT",
+    "BINARY",
+    "GEOGRAPHY",
+    "GEOMETRY",
+];
+
+/// How many pages are requested from the live documentation at the same time
+const CONCURRENT_REQUESTS: usize = 8;
+
+/// How often a request for a page is tried before it is given up on, waiting a little longer after every attempt
+const ATTEMPTS: u32 = 3;
+const RETRY_DELAY: Duration = Duration::from_millis(500);
+
+#[derive(Debug)]
+pub enum ScrapeError {
+    Http(reqwest::Error),
+    Io(io::Error),
+    /// A page does not have the expected structure
+    Page { page: String, message: String },
+}
+
+impl Display for ScrapeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ScrapeError::Http(e) => write!(f, "{}", e),
+            ScrapeError::Io(e) => write!(f, "{}", e),
+            ScrapeError::Page { page, message } => write!(f, "{}: {}", page, message),
         }
+    }
+}
 
+impl From<reqwest::Error> for ScrapeError {
+    fn from(e: reqwest::Error) -> Self {
+        ScrapeError::Http(e)
+    }
+}
 
+impl From<io::Error> for ScrapeError {
+    fn from(e: io::Error) -> Self {
+        ScrapeError::Io(e)
+    }
+}
 
+/// Where the pages of the SQL reference are read from
+pub enum Pages {
+    /// The live documentation, under this URL
+    Live(String),
+    /// Pages saved to a directory as `<page>.html`, like `functions-aggregation.html` and `functions/count.html`
+    Saved(PathBuf),
+}
 
-        if function_definition.len() > 2 {
-            println!("More than 2 definitions for function {:?} - {:?}", aggregate_functions[i], function_definition)
+impl Pages {
+    /// A page by its path in the SQL reference, like `functions/count`
+    async fn get(&self, page: &str) -> Result<String, ScrapeError> {
+        match self {
+            Pages::Live(url) => {
+                let url = format!("{}/{}", url, page);
+                let mut attempt = 1;
+                loop {
+                    let response = reqwest::get(&url).await.and_then(|response| response.error_for_status());
+                    match response {
+                        Ok(response) => return Ok(response.text().await?),
+                        // A missing page will not turn up, but the site may be busy or the connection broken
+                        Err(e) if attempt < ATTEMPTS && e.status().is_none_or(retry_status) => {
+                            tokio::time::sleep(RETRY_DELAY * attempt).await;
+                            attempt += 1;
+                        }
+                        Err(e) => return Err(e.into()),
+                    }
+                }
+            }
+            Pages::Saved(dir) => Ok(fs::read_to_string(dir.join(format!("{}.html", page)))?),
         }
     }
+}
+
+/// Whether a request that failed with the status is worth trying again
+fn retry_status(status: reqwest::StatusCode) -> bool {
+    status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS
+}
+
+fn page_error(page: &str, message: &str) -> ScrapeError {
+    ScrapeError::Page {
+        page: page.to_string(),
+        message: message.to_string(),
+    }
+}
+
+fn parse_html<'a>(html: &'a str, page: &str) -> Result<VDom<'a>, ScrapeError> {
+    tl::parse(html, ParserOptions::default()).map_err(|e| page_error(page, &e.to_string()))
+}
+
+/// The elements matching the selector. tl only matches simple selectors, so a descendant selector like
+/// `section p` is matched one part at a time.
+fn select(dom: &VDom, selector: &str) -> Vec<NodeHandle> {
+    let parser = dom.parser();
+    let mut parts = selector.split_whitespace();
+    let mut handles: Vec<NodeHandle> = parts
+        .next()
+        .and_then(|part| dom.query_selector(part))
+        .into_iter()
+        .flatten()
+        .collect();
+    for part in parts {
+        let mut seen = HashSet::new();
+        handles = handles
+            .iter()
+            .filter_map(|handle| handle.get(parser)?.as_tag()?.query_selector(parser, part))
+            .flatten()
+            .filter(|handle| seen.insert(handle.get_inner()))
+            .collect();
+    }
+    handles
+}
+
+/// The text of the elements matching the selector, with the entities the documentation uses decoded
+fn texts(dom: &VDom, selector: &str) -> Vec<String> {
+    let parser = dom.parser();
+    select(dom, selector)
+        .into_iter()
+        .filter_map(|handle| handle.get(parser))
+        .map(|node| decode_entities(&node.inner_text(parser)))
+        .collect()
+}
+
+fn decode_entities(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&#x27;", "'")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+}
+
+/// The functions a category page lists, one line of comma separated names at a time
+fn parse_function_list(html: &str, page: &str) -> Result<Vec<String>, ScrapeError> {
+    let dom = parse_html(html, page)?;
+    Ok(texts(&dom, ".line")
+        .iter()
+        .flat_map(|line| line.split(','))
+        .map(|function| function.trim().to_uppercase())
+        .filter(|function| !function.is_empty())
+        .collect())
+}
 
-    let aggregate_function_definitions = get_aggregate_function_definition("https://docs.snowflake.com/en/sql-reference/functions/any_value").await;
-    println!("{:?}", aggregate_function_definitions);
-}
\ No newline at end of file
+/// Splits a syntax block into its signatures. The lines of a signature after the first are indented, and a block
+/// can hold several signatures, like the ones of GET for arrays and objects.
+fn split_signatures(block: &str, name: &str) -> Vec<String> {
+    let mut signatures: Vec<String> = vec![];
+    for line in block.lines().filter(|line| !line.trim().is_empty()) {
+        match signatures.last_mut() {
+            Some(signature) if !line.starts_with(name) => {
+                signature.push('\n');
+                signature.push_str(line);
+            }
+            _ => signatures.push(line.to_string()),
+        }
+    }
+    signatures
+}
+
+/// The first data type named in the description of what a function returns
+fn return_type(returns: &str) -> Option<String> {
+    let words: HashSet<&str> = returns
+        .split(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+        .collect();
+    DATA_TYPES
+        .iter()
+        .find(|data_type| words.contains(*data_type))
+        .map(|data_type| data_type.to_string())
+}
+
+/// Reads the summary, signatures, return type and output columns of a function from its page
+fn parse_function_page(html: &str, page: &str, name: &str, category: &str) -> Result<CatalogFunction, ScrapeError> {
+    let dom = parse_html(html, page)?;
+    let summary = texts(&dom, "section p")
+        .into_iter()
+        .next()
+        .map(|summary| summary.split_whitespace().collect::<Vec<_>>().join(" "))
+        .unwrap_or_default();
+    let signatures = texts(&dom, ".highlight-sqlsyntax")
+        .iter()
+        .flat_map(|block| split_signatures(block, name))
+        .map(|syntax| parse_signature(&syntax).map_err(|e| page_error(page, &e)))
+        .collect::<Result<Vec<_>, _>>()?;
+    if signatures.is_empty() {
+        return Err(page_error(page, "no syntax found"));
+    }
+    let returns = texts(&dom, "#returns p").first().and_then(|returns| return_type(returns));
+    // The first cell of each row of the output table is the name of a column
+    let parser = dom.parser();
+    let columns = select(&dom, "#output tr")
+        .into_iter()
+        .filter_map(|row| row.get(parser)?.as_tag()?.query_selector(parser, "td")?.next())
+        .filter_map(|cell| cell.get(parser))
+        .map(|cell| cell.inner_text(parser).trim().to_uppercase())
+        .collect();
+    Ok(CatalogFunction {
+        name: name.to_string(),
+        category: category.to_string(),
+        summary,
+        returns,
+        signatures,
+        columns,
+    })
+}
+
+/// Scrapes every function of the SQL reference. Pages that are missing or cannot be read are left out of the catalog
+/// and returned with it.
+pub async fn scrape(pages: &Pages) -> (Catalog, Vec<ScrapeError>) {
+    let mut errors = vec![];
+    let mut listed: Vec<(String, &str)> = vec![];
+    let mut seen = HashSet::new();
+    for (category, page) in CATEGORIES {
+        let functions = match pages.get(page).await {
+            Ok(html) => parse_function_list(&html, page),
+            Err(e) => Err(e),
+        };
+        match functions {
+            Ok(functions) => listed.extend(
+                functions
+                    .into_iter()
+                    .filter(|function| seen.insert(function.clone()))
+                    .map(|function| (function, category)),
+            ),
+            Err(e) => errors.push(e),
+        }
+    }
+
+    // The SQL reference lists hundreds of functions, so only a few of their pages are requested at a time
+    let function_pages: Vec<_> = stream::iter(listed.iter().map(|(name, _)| async move {
+        let page = format!("functions/{}", name.to_lowercase());
+        let html = pages.get(&page).await;
+        (page, html)
+    }))
+    .buffered(CONCURRENT_REQUESTS)
+    .collect()
+    .await;
+    let mut functions = vec![];
+    for ((name, category), (page, html)) in listed.iter().zip(function_pages) {
+        match html.and_then(|html| parse_function_page(&html, &page, name, category)) {
+            Ok(function) => functions.push(function),
+            Err(e) => errors.push(e),
+        }
+    }
+    functions.sort_by(|a, b| a.name.cmp(&b.name));
+    let catalog = Catalog {
+        version: CATALOG_VERSION,
+        functions,
+    };
+    (catalog, errors)
+}
+
+/// `dbt-lsp catalog [--pages <dir>] [--out <file>]`: scrapes the SQL reference, or the pages saved in `dir`, and
+/// writes the catalog the build compiles in
+pub async fn catalog_command(args: &[String]) -> Result<(), String> {
+    let mut pages = Pages::Live(Dialect::Snowflake.sql_reference().unwrap_or_default().to_string());
+    let mut out = PathBuf::from("catalog/snowflake.json");
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--pages" => pages = Pages::Saved(value()?.into()),
+            "--out" => out = value()?.into(),
+            _ => return Err(format!("unknown argument {}", arg)),
+        }
+    }
+
+    let (catalog, errors) = scrape(&pages).await;
+    for e in &errors {
+        eprintln!("skipped {}", e);
+    }
+    if catalog.functions.is_empty() {
+        return Err("no functions were found".into());
+    }
+    let json = serde_json::to_string_pretty(&catalog).map_err(|e| e.to_string())?;
+    fs::write(&out, json + "\n").map_err(|e| format!("could not write {}: {}", out.display(), e))?;
+    eprintln!("wrote {} functions to {}", catalog.functions.len(), out.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixtures() -> Pages {
+        Pages::Saved("./test_sql/snowflake_docs".into())
+    }
+
+    #[tokio::test]
+    async fn test_scrape_fixtures() {
+        let (catalog, errors) = scrape(&fixtures()).await;
+        assert_eq!(catalog.version, CATALOG_VERSION);
+        let names: Vec<(&str, &str)> = catalog
+            .functions
+            .iter()
+            .map(|function| (function.name.as_str(), function.category.as_str()))
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                ("COUNT", "aggregate"),
+                ("FLATTEN", "table"),
+                ("GET", "semi-structured"),
+                ("LAG", "window"),
+                ("LISTAGG", "aggregate"),
+            ]
+        );
+        // The fixtures only have a few of the category pages, and lack the page of one listed function
+        let missing: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(missing.len(), CATEGORIES.len() - 4 + 1, "{:?}", missing);
+
+        let count = &catalog.functions[0];
+        assert_eq!(
+            count.summary,
+            "Returns either the number of non-NULL records for the specified columns, or the total number of records."
+        );
+        assert_eq!(count.returns.as_deref(), Some("NUMBER"));
+        let syntax: Vec<&str> = count.signatures.iter().map(|s| s.syntax.as_str()).collect();
+        assert_eq!(
+            syntax,
+            vec![
+                "COUNT( [ DISTINCT ] <expr1> [ , <expr2> ... ] )",
+                "COUNT( * )",
+                "COUNT( [ DISTINCT ] <expr1> [ , <expr2> ... ] ) OVER ( [ PARTITION BY <expr3> ] [ ORDER BY <expr4> [ ASC | DESC ] [ <window_frame> ] ] )",
+            ]
+        );
+        assert!(count.signatures[0].distinct && !count.signatures[0].window);
+        assert!(count.signatures[2].window);
+
+        let flatten = &catalog.functions[1];
+        assert_eq!(flatten.columns, vec!["SEQ", "KEY", "PATH", "INDEX", "VALUE", "THIS"]);
+        assert_eq!(flatten.signatures[0].arguments.len(), 3);
+
+        let get = &catalog.functions[2];
+        assert_eq!(get.signatures.len(), 2);
+        assert_eq!(get.returns.as_deref(), Some("VARIANT"));
+
+        let lag = &catalog.functions[3];
+        assert!(lag.signatures[0].window);
+        assert!(lag.summary.starts_with("Accesses data in a previous row"));
+    }
+
+    #[tokio::test]
+    async fn test_catalog_command() {
+        let out = std::env::temp_dir().join(format!("dbt-lsp-catalog-{}.json", std::process::id()));
+        let args: Vec<String> = vec![
+            "--pages".into(),
+            "./test_sql/snowflake_docs".into(),
+            "--out".into(),
+            out.to_string_lossy().into_owned(),
+        ];
+        catalog_command(&args).await.unwrap();
+        let catalog: Catalog = serde_json::from_str(&fs::read_to_string(&out).unwrap()).unwrap();
+        fs::remove_file(&out).unwrap();
+        assert_eq!(catalog.functions.len(), 5);
+
+        assert_eq!(
+            catalog_command(&["--pages".into()]).await,
+            Err("--pages needs a value".into())
+        );
+        assert_eq!(
+            catalog_command(&["--pages".into(), "./does/not/exist".into()]).await,
+            Err("no functions were found".into())
+        );
+    }
+}
@@ -0,0 +1,187 @@
+use pest::{iterators::Pairs, Parser};
+use pest_derive::Parser;
+
+use crate::functions::catalog::{CatalogArgument, CatalogSignature};
+
+#[derive(Parser)]
+#[grammar = "webscraping/snowflake_doc.pest"]
+struct SnowflakeDocParser;
+
+/// What has been read of an argument list so far
+#[derive(Default)]
+struct Arguments {
+    arguments: Vec<CatalogArgument>,
+    /// Whether the part of the list since the last comma already has an argument
+    in_argument: bool,
+    /// The placeholder that ends a repetition, like `<exprN>` in `[ , ... , <exprN> ]`, is not another argument
+    after_ellipsis: bool,
+    distinct: bool,
+}
+
+impl Arguments {
+    fn argument(&mut self, name: String, optional: bool) {
+        if std::mem::take(&mut self.after_ellipsis) || self.in_argument {
+            self.in_argument = true;
+            return;
+        }
+        self.in_argument = true;
+        // `[<key>, <value> [, <key>, <value> , ...]]` repeats the arguments it names again
+        if let Some(argument) = self.arguments.iter_mut().find(|argument| argument.name == name) {
+            argument.variadic = true;
+            return;
+        }
+        self.arguments.push(CatalogArgument {
+            name,
+            optional,
+            variadic: false,
+        });
+    }
+
+    fn read(&mut self, pairs: Pairs<Rule>, optional: bool) {
+        for pair in pairs {
+            match pair.as_rule() {
+                Rule::separator => self.in_argument = false,
+                Rule::optional => self.read(pair.into_inner(), true),
+                Rule::distinct => self.distinct = true,
+                Rule::ellipsis => {
+                    if let Some(argument) = self.arguments.last_mut() {
+                        argument.variadic = true;
+                    }
+                    self.after_ellipsis = true;
+                }
+                Rule::named_arg => {
+                    let name = pair.into_inner().as_str().to_lowercase();
+                    self.argument(name, optional);
+                }
+                Rule::expr => {
+                    let name = pair.into_inner().as_str().to_string();
+                    self.argument(name, optional);
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+fn contains_over_clause(pairs: Pairs<Rule>) -> bool {
+    pairs.flatten().any(|pair| pair.as_rule() == Rule::over_clause)
+}
+
+/// Parses a syntax line of the documentation, like `ROUND( <input_expr> [ , <scale_expr> ] )`, into the arguments of
+/// the signature it describes
+pub fn parse_signature(syntax: &str) -> Result<CatalogSignature, String> {
+    let syntax = syntax.split_whitespace().collect::<Vec<_>>().join(" ");
+    let definition = SnowflakeDocParser::parse(Rule::function_definition, &syntax)
+        .map_err(|e| format!("could not parse `{}`: {}", syntax, e))?
+        .next()
+        .ok_or_else(|| format!("could not parse `{}`", syntax))?;
+
+    let mut arguments = Arguments::default();
+    let mut window = false;
+    for pair in definition.into_inner() {
+        match pair.as_rule() {
+            Rule::function_arg_list => arguments.read(pair.into_inner(), false),
+            Rule::over_clause => window = true,
+            _ => window |= contains_over_clause(pair.into_inner()),
+        }
+    }
+    Ok(CatalogSignature {
+        syntax,
+        arguments: arguments.arguments,
+        distinct: arguments.distinct,
+        window,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn arguments(syntax: &str) -> Vec<(String, bool, bool)> {
+        parse_signature(syntax)
+            .unwrap()
+            .arguments
+            .into_iter()
+            .map(|argument| (argument.name, argument.optional, argument.variadic))
+            .collect()
+    }
+
+    fn argument(name: &str, optional: bool, variadic: bool) -> (String, bool, bool) {
+        (name.to_string(), optional, variadic)
+    }
+
+    #[test]
+    fn test_parse_signature() {
+        assert_eq!(
+            arguments("DATEADD( <date_or_time_part>, <value>, <date_or_time_expr> )"),
+            vec![
+                argument("date_or_time_part", false, false),
+                argument("value", false, false),
+                argument("date_or_time_expr", false, false),
+            ]
+        );
+        assert_eq!(
+            arguments("ROUND( <input_expr> [ , <scale_expr> [ , <rounding_mode> ] ] )"),
+            vec![
+                argument("input_expr", false, false),
+                argument("scale_expr", true, false),
+                argument("rounding_mode", true, false),
+            ]
+        );
+        assert_eq!(
+            arguments("COALESCE( <expr1> , <expr2> [ , ... , <exprN> ] )"),
+            vec![argument("expr1", false, false), argument("expr2", false, true)]
+        );
+        assert_eq!(
+            arguments("OBJECT_CONSTRUCT( [<key>, <value> [, <key>, <value> , ...]] )"),
+            vec![argument("key", true, true), argument("value", true, true)]
+        );
+        assert_eq!(
+            arguments("FLATTEN( INPUT => <expr> [ , PATH => <constant_expr> ] [ , OUTER => TRUE | FALSE ] [ , MODE => 'OBJECT' | 'ARRAY' | 'BOTH' ] )"),
+            vec![
+                argument("input", false, false),
+                argument("path", true, false),
+                argument("outer", true, false),
+                argument("mode", true, false),
+            ]
+        );
+        assert_eq!(
+            arguments("TO_CHAR( <expr> [, '<format>' ] )"),
+            vec![argument("expr", false, false), argument("format", true, false)]
+        );
+        assert_eq!(
+            arguments("CAST( <source_expr> AS <target_data_type> )"),
+            vec![argument("source_expr", false, false)]
+        );
+        assert_eq!(arguments("COUNT( * )"), vec![]);
+        assert_eq!(arguments("CURRENT_DATE()"), vec![]);
+        assert_eq!(arguments("CURRENT_DATE"), vec![]);
+    }
+
+    #[test]
+    fn test_distinct_and_windows() {
+        let count = parse_signature("COUNT( [ DISTINCT ] <expr1> [ , <expr2> ... ] )").unwrap();
+        assert!(count.distinct);
+        assert!(!count.window);
+        assert_eq!(count.arguments.len(), 2);
+
+        let lag = parse_signature(
+            "LAG( <expr> [ , <offset> , <default> ] ) [ { IGNORE | RESPECT } NULLS ]\n    OVER ( [ PARTITION BY <expr1> ] ORDER BY <expr2> [ { ASC | DESC } ] )",
+        )
+        .unwrap();
+        assert!(lag.window);
+        assert!(!lag.distinct);
+        assert_eq!(
+            lag.syntax,
+            "LAG( <expr> [ , <offset> , <default> ] ) [ { IGNORE | RESPECT } NULLS ] OVER ( [ PARTITION BY <expr1> ] ORDER BY <expr2> [ { ASC | DESC } ] )"
+        );
+        assert_eq!(lag.arguments.len(), 3);
+
+        let ratio = parse_signature("RATIO_TO_REPORT( <expr1> ) [ OVER ( [ PARTITION BY <expr2> ] [ ORDER BY <expr3> ] ) ]").unwrap();
+        assert!(ratio.window);
+        let listagg = parse_signature("LISTAGG( [ DISTINCT ] <expr1> [, <delimiter> ] ) [ WITHIN GROUP ( <orderby_clause> ) ]").unwrap();
+        assert!(listagg.distinct && !listagg.window);
+
+        assert!(parse_signature("not a signature").is_err());
+    }
+}
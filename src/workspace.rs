@@ -0,0 +1,375 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use fuzzy_matcher::{skim::SkimMatcherV2, FuzzyMatcher};
+use serde::Deserialize;
+use tower_lsp::lsp_types::{Location, Position, Range, SymbolInformation, SymbolKind, Url};
+use walkdir::WalkDir;
+
+use crate::{
+    dialect::Dialect,
+    jinja_parser::{JinjaBlockKind, JinjaParser},
+    parser::{self, Model},
+    utils::Span,
+};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ResourceKind {
+    File,
+    Model,
+    Source,
+    Macro,
+    Seed,
+    Test,
+}
+
+impl ResourceKind {
+    fn symbol_kind(self) -> SymbolKind {
+        match self {
+            ResourceKind::File => SymbolKind::FILE,
+            ResourceKind::Model | ResourceKind::Source | ResourceKind::Seed => SymbolKind::STRUCT,
+            ResourceKind::Macro => SymbolKind::FUNCTION,
+            ResourceKind::Test => SymbolKind::METHOD,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            ResourceKind::File => "file",
+            ResourceKind::Model => "model",
+            ResourceKind::Source => "source",
+            ResourceKind::Macro => "macro",
+            ResourceKind::Seed => "seed",
+            ResourceKind::Test => "test",
+        }
+    }
+}
+
+/// A named resource of a dbt project and where it is defined
+#[derive(Debug)]
+pub struct Resource {
+    pub name: String,
+    pub kind: ResourceKind,
+    pub path: PathBuf,
+    pub range: Range,
+}
+
+fn default_model_paths() -> Vec<String> {
+    vec!["models".into()]
+}
+
+fn default_macro_paths() -> Vec<String> {
+    vec!["macros".into()]
+}
+
+fn default_seed_paths() -> Vec<String> {
+    vec!["seeds".into()]
+}
+
+fn default_test_paths() -> Vec<String> {
+    vec!["tests".into()]
+}
+
+/// The parts of `dbt_project.yml` needed to find the resources of a project
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct ProjectConfig {
+    #[serde(default = "default_model_paths")]
+    model_paths: Vec<String>,
+    #[serde(default = "default_macro_paths")]
+    macro_paths: Vec<String>,
+    #[serde(default = "default_seed_paths")]
+    seed_paths: Vec<String>,
+    #[serde(default = "default_test_paths")]
+    test_paths: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct SourceFile {
+    #[serde(default)]
+    sources: Vec<SourceConfig>,
+}
+
+#[derive(Deserialize)]
+struct SourceConfig {
+    name: String,
+    #[serde(default)]
+    tables: Vec<TableConfig>,
+}
+
+#[derive(Deserialize)]
+struct TableConfig {
+    name: String,
+}
+
+/// All models, sources, macros, seeds and tests of the dbt projects in a workspace
+#[derive(Default)]
+pub struct WorkspaceIndex {
+    pub models: HashMap<String, Model>,
+    resources: Vec<Resource>,
+}
+
+fn files_with_extension(root: &Path, paths: &[String], extension: &str) -> Vec<PathBuf> {
+    paths
+        .iter()
+        .flat_map(|path| WalkDir::new(root.join(path)).into_iter().flatten())
+        .map(|entry| entry.into_path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == extension))
+        .collect()
+}
+
+fn file_stem(path: &Path) -> String {
+    path.file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+/// Finds the line of the first `name: <name>` entry at or after `from_line`
+fn find_name_line(text: &str, name: &str, from_line: usize) -> Option<usize> {
+    text.lines()
+        .enumerate()
+        .skip(from_line)
+        .find(|(_, line)| {
+            let line = line.trim().trim_start_matches('-').trim_start();
+            let Some(value) = line.strip_prefix("name:") else {
+                return false;
+            };
+            value.trim().trim_matches(|c| c == '\'' || c == '"') == name
+        })
+        .map(|(i, _)| i)
+}
+
+/// How well a name matches a query: whether it contains every character of the query, then the matcher's score
+type Score = (bool, i64);
+
+/// Queries this long match names that lack one of their characters other than the first, like "cust" and
+/// `cents_to_dollars`
+const MIN_TYPO_QUERY: usize = 4;
+
+fn fuzzy_score(matcher: &SkimMatcherV2, name: &str, query: &str) -> Option<Score> {
+    if let Some(score) = matcher.fuzzy_match(name, query) {
+        return Some((true, score));
+    }
+    let chars: Vec<char> = query.chars().collect();
+    if chars.len() < MIN_TYPO_QUERY {
+        return None;
+    }
+    (1..chars.len())
+        .filter_map(|missing| {
+            let pattern: String = [&chars[..missing], &chars[missing + 1..]].concat().into_iter().collect();
+            matcher.fuzzy_match(name, &pattern)
+        })
+        .max()
+        .map(|score| (false, score))
+}
+
+fn line_range(line: usize) -> Range {
+    let position = Position {
+        line: line as u32,
+        character: 0,
+    };
+    Range {
+        start: position,
+        end: position,
+    }
+}
+
+impl WorkspaceIndex {
+    /// Indexes every dbt project (a directory with a `dbt_project.yml`) below `root`, parsing models as `dialect`
+    pub fn index(root: &Path, dialect: Dialect) -> WorkspaceIndex {
+        let mut index = WorkspaceIndex::default();
+        let projects = WalkDir::new(root)
+            .into_iter()
+            .filter_entry(|entry| {
+                let name = entry.file_name().to_string_lossy();
+                !matches!(name.as_ref(), "target" | "dbt_packages" | "node_modules" | ".git")
+            })
+            .flatten()
+            .filter(|entry| entry.file_name() == "dbt_project.yml");
+        for project in projects {
+            let Some(project_root) = project.path().parent() else {
+                continue;
+            };
+            index.index_project(project_root, dialect);
+        }
+        index
+    }
+
+    fn index_project(&mut self, root: &Path, dialect: Dialect) {
+        let config: ProjectConfig = match fs::read_to_string(root.join("dbt_project.yml"))
+            .ok()
+            .and_then(|text| serde_yaml::from_str(&text).ok())
+        {
+            Some(config) => config,
+            None => return,
+        };
+
+        for path in files_with_extension(root, &config.model_paths, "sql") {
+            self.index_model(&path, dialect);
+        }
+        for path in files_with_extension(root, &config.model_paths, "yml") {
+            self.index_sources(&path);
+        }
+        for path in files_with_extension(root, &config.macro_paths, "sql") {
+            self.index_macros(&path);
+        }
+        for path in files_with_extension(root, &config.seed_paths, "csv") {
+            self.push(file_stem(&path), ResourceKind::Seed, path, Range::default());
+        }
+        for path in files_with_extension(root, &config.test_paths, "sql") {
+            self.push(file_stem(&path), ResourceKind::Test, path, Range::default());
+        }
+    }
+
+    fn push(&mut self, name: String, kind: ResourceKind, path: PathBuf, range: Range) {
+        self.resources.push(Resource {
+            name,
+            kind,
+            path,
+            range,
+        });
+    }
+
+    fn index_model(&mut self, path: &Path, dialect: Dialect) {
+        let name = file_stem(path);
+        let file_name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        self.push(file_name, ResourceKind::File, path.into(), Range::default());
+        self.push(name.clone(), ResourceKind::Model, path.into(), Range::default());
+
+        let Ok(src) = fs::read_to_string(path) else {
+            return;
+        };
+        let mut jinja_parse = JinjaParser::new(&src);
+        if jinja_parse.render_jinja().is_err() {
+            return;
+        }
+        // Models with syntax errors are kept with what could be parsed of them
+        let model = parser::parse_sql(&jinja_parse, &name, dialect).model;
+        self.models.insert(name, model);
+    }
+
+    fn index_sources(&mut self, path: &Path) {
+        let Ok(text) = fs::read_to_string(path) else {
+            return;
+        };
+        let Ok(source_file) = serde_yaml::from_str::<SourceFile>(&text) else {
+            return;
+        };
+        for source in source_file.sources {
+            let source_line = find_name_line(&text, &source.name, 0).unwrap_or_default();
+            for table in source.tables {
+                let line = find_name_line(&text, &table.name, source_line + 1).unwrap_or(source_line);
+                self.push(
+                    format!("{}.{}", source.name, table.name),
+                    ResourceKind::Source,
+                    path.into(),
+                    line_range(line),
+                );
+            }
+        }
+    }
+
+    fn index_macros(&mut self, path: &Path) {
+        let Ok(src) = fs::read_to_string(path) else {
+            return;
+        };
+        let mut jinja_parse = JinjaParser::new(&src);
+        if jinja_parse.render_jinja().is_err() {
+            return;
+        }
+        let macros: Vec<(String, Range)> = jinja_parse
+            .blocks()
+            .iter()
+            .filter_map(|block| match &block.kind {
+                JinjaBlockKind::Macro { name, .. } => {
+                    Some((name.clone(), Span::from_span(block.span).to_range()))
+                }
+                JinjaBlockKind::Config => None,
+            })
+            .collect();
+        for (name, range) in macros {
+            self.push(name, ResourceKind::Macro, path.into(), range);
+        }
+    }
+
+    /// Fuzzy matches `query` against every resource name, best matches first. Names that only match with one
+    /// character of the query missing come after all others.
+    pub fn symbols(&self, query: &str) -> Vec<SymbolInformation> {
+        let matcher = SkimMatcherV2::default().ignore_case();
+
+        let mut matches: Vec<(Score, &Resource)> = self
+            .resources
+            .iter()
+            .filter_map(|resource| fuzzy_score(&matcher, &resource.name, query).map(|score| (score, resource)))
+            .collect();
+        matches.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.name.cmp(&b.1.name)));
+
+        matches
+            .into_iter()
+            .filter_map(|(_, resource)| {
+                let uri = Url::from_file_path(&resource.path).ok()?;
+                #[allow(deprecated)]
+                Some(SymbolInformation {
+                    name: resource.name.clone(),
+                    kind: resource.kind.symbol_kind(),
+                    tags: None,
+                    deprecated: None,
+                    location: Location {
+                        uri,
+                        range: resource.range,
+                    },
+                    container_name: Some(resource.kind.label().into()),
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_workspace_symbols() {
+        let root = fs::canonicalize("test_sql/jaffa_shop").unwrap();
+        let index = WorkspaceIndex::index(&root, Dialect::Snowflake);
+        assert!(index.models.contains_key("stg_customers"));
+
+        let symbols = index.symbols("cust");
+        let found: Vec<(&str, &str)> = symbols
+            .iter()
+            .map(|symbol| {
+                (
+                    symbol.name.as_str(),
+                    symbol.container_name.as_deref().unwrap_or_default(),
+                )
+            })
+            .collect();
+        for expected in [
+            ("customers.sql", "file"),
+            ("stg_customers", "model"),
+            ("cents_to_dollars", "macro"),
+            ("raw_customers", "seed"),
+            ("jaffle_shop.customers", "source"),
+        ] {
+            assert!(found.contains(&expected), "{:?} not in {:?}", expected, found);
+        }
+        // The macro lacks the "u" of the query, so it comes after every name that has all of it
+        assert_eq!(found.last(), Some(&("cents_to_dollars", "macro")));
+        // Short queries, and the first character of a query, have to match
+        assert!(index.symbols("cus").iter().all(|symbol| symbol.name != "cents_to_dollars"));
+        assert!(index.symbols("xust").is_empty());
+
+        let source = symbols
+            .iter()
+            .find(|symbol| symbol.name == "jaffle_shop.customers")
+            .unwrap();
+        assert_eq!(source.location.range.start.line, 6);
+    }
+}
@@ -0,0 +1,3 @@
+{% macro cents_to_dollars(column_name, precision=2) -%}
+    ({{ column_name }} / 100)::numeric(16, {{ precision }})
+{%- endmacro %}